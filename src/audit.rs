@@ -0,0 +1,370 @@
+use crate::scan::Candidate;
+use crate::units::Units;
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct HistoryArgs {
+    #[structopt(long, help = "Lists every path removed by each run")]
+    pub paths: bool,
+
+    #[structopt(long, help = "Only shows the most recent N runs")]
+    pub last: Option<usize>,
+}
+
+// Single path removed, failed to be removed, or skipped by a nuke
+// One entry is appended per path as soon as it's removed, so an interrupted run is still recorded.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    // Milliseconds since the unix epoch when the run started, and the id of the process that ran it
+    // Together they identify a run, since concurrent runs can start in the same millisecond. `pid` is
+    // missing from older logs.
+    pub run: u64,
+    #[serde(default)]
+    pub pid: u32,
+
+    // Seconds since the unix epoch when the path was removed
+    pub timestamp: u64,
+
+    pub user: String,
+    pub starting_dir: PathBuf,
+    pub path: PathBuf,
+    pub bytes: u64,
+
+    // Glob that ignored the path and the file it came from
+    pub glob: Option<String>,
+    pub source: Option<String>,
+
+    // None if the path was removed
+    pub error: Option<String>,
+
+    // Why the path was left alone because it changed since the scan. Missing from older logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
+}
+
+// What happened to a candidate when it was nuked
+#[derive(Debug)]
+pub enum Outcome {
+    Removed,
+    Failed(String),
+
+    // Left alone because it changed since the scan, see `Revalidator`
+    Skipped(String),
+}
+
+// Append-only log of every path nuked
+pub struct AuditLog {
+    file: File,
+    run: u64,
+    pid: u32,
+    user: String,
+}
+
+impl AuditLog {
+    // Open the log for a new run, creating it if needed
+    pub fn open() -> anyhow::Result<AuditLog> {
+        let path = log_path().ok_or_else(|| anyhow!("Failed to find state directory"))?;
+        AuditLog::open_at(&path)
+    }
+
+    fn open_at(path: &Path) -> anyhow::Result<AuditLog> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log [{}]", path.display()))?;
+
+        // End a line cut short by a crash so it doesn't swallow the next entry
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+            }
+        }
+        let user = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_owned());
+        Ok(AuditLog {
+            file,
+            run: unix_time().as_millis() as u64,
+            pid: std::process::id(),
+            user,
+        })
+    }
+
+    // Record the outcome of removing `candidate`
+    pub fn record(&mut self, candidate: &Candidate, starting_dir: &Path, outcome: Outcome) -> anyhow::Result<()> {
+        let (error, skipped) = match outcome {
+            Outcome::Removed => (None, None),
+            Outcome::Failed(error) => (Some(error), None),
+            Outcome::Skipped(reason) => (None, Some(reason)),
+        };
+        let entry = AuditEntry {
+            run: self.run,
+            pid: self.pid,
+            timestamp: unix_time().as_secs(),
+            user: self.user.clone(),
+            starting_dir: starting_dir.to_owned(),
+            path: candidate.path.clone(),
+            bytes: candidate.size,
+            glob: candidate.glob.clone(),
+            source: candidate.source.as_ref().map(ToString::to_string),
+            error,
+            skipped,
+        };
+        let line = serde_json::to_string(&entry)? + "\n";
+        self.file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+// Every entry in the log at `path`, oldest first
+// Lines that can't be parsed, such as one cut short by a crash, are skipped.
+fn load(path: &Path) -> anyhow::Result<Vec<AuditEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read audit log [{}]", path.display())),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+// Entries grouped by run, in order of each run's first entry
+// Concurrent runs append to the same log, so their entries can be interleaved.
+fn runs(entries: &[AuditEntry]) -> Vec<Vec<&AuditEntry>> {
+    let mut runs: Vec<Vec<&AuditEntry>> = Vec::new();
+    let mut index: HashMap<(u64, u32), usize> = HashMap::new();
+    for entry in entries {
+        let idx = *index.entry((entry.run, entry.pid)).or_insert_with(|| {
+            runs.push(Vec::new());
+            runs.len() - 1
+        });
+        runs[idx].push(entry);
+    }
+    runs
+}
+
+// Bytes reclaimed by the entries that were removed
+fn reclaimed<'a>(entries: impl IntoIterator<Item = &'a AuditEntry>) -> u64 {
+    entries
+        .into_iter()
+        .filter(|e| e.error.is_none() && e.skipped.is_none())
+        .map(|e| e.bytes)
+        .sum()
+}
+
+// Print past runs and the total space they reclaimed
+pub fn run(args: &HistoryArgs, units: Units) -> anyhow::Result<()> {
+    let path = log_path().ok_or_else(|| anyhow!("Failed to find state directory"))?;
+    let entries = load(&path)?;
+    if entries.is_empty() {
+        println!("Nothing has been nuked yet.");
+        return Ok(());
+    }
+
+    let runs = runs(&entries);
+    let shown = args.last.map_or(runs.len(), |last| last.min(runs.len()));
+    println!(
+        "  {:23} {:12} {:>10} {:>8} {:>8} {:>8}",
+        "Started", "User", "Reclaimed", "Paths", "Failed", "Skipped"
+    );
+    for run in &runs[runs.len() - shown..] {
+        let failed = run.iter().filter(|e| e.error.is_some()).count();
+        let skipped = run.iter().filter(|e| e.skipped.is_some()).count();
+        println!(
+            "  {:23} {:12} {:>10} {:>8} {:>8} {:>8}",
+            format_timestamp(run[0].run / 1000),
+            run[0].user,
+            units.format(reclaimed(run.iter().copied())),
+            run.len(),
+            failed,
+            skipped
+        );
+        if args.paths {
+            for entry in run.iter() {
+                match (&entry.error, &entry.skipped) {
+                    (Some(error), _) => println!("      {:10} {:?} failed: {}", "", entry.path, error),
+                    (None, Some(reason)) => println!("      {:10} {:?} skipped: {}", "", entry.path, reason),
+                    (None, None) => println!("      {:10} {:?}", units.format(entry.bytes), entry.path),
+                }
+            }
+        }
+    }
+    println!(
+        "Total reclaimed by {} runs: {}",
+        runs.len(),
+        units.format(reclaimed(&entries))
+    );
+    println!("Log: [{}]", path.display());
+    Ok(())
+}
+
+// The log lives in `$FTS_GITIGNORE_NUKE_STATE_DIR` if set, or else the platform's state directory
+fn log_path() -> Option<PathBuf> {
+    env::var_os("FTS_GITIGNORE_NUKE_STATE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .map(|dir| dir.join("fts_gitignore_nuke"))
+        })
+        .map(|dir| dir.join("audit.jsonl"))
+}
+
+fn unix_time() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+// Format seconds since the unix epoch as a UTC date and time, such as `2024-03-01 17:05:09 UTC`
+fn format_timestamp(secs: u64) -> String {
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_709_312_709), "2024-03-01 17:05:09 UTC");
+    }
+
+    #[test]
+    fn log_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("state").join("audit.jsonl");
+        let candidate = |path: &str, size| Candidate {
+            size,
+            glob: Some("target/".to_owned()),
+            ..Candidate::new(PathBuf::from(path))
+        };
+
+        let mut log = AuditLog::open_at(&path).unwrap();
+        log.record(&candidate("/src/a/target", 100), Path::new("/src"), Outcome::Removed)
+            .unwrap();
+        log.record(
+            &candidate("/src/b/target", 50),
+            Path::new("/src"),
+            Outcome::Failed("permission denied".to_owned()),
+        )
+        .unwrap();
+        log.record(
+            &candidate("/src/d/target", 20),
+            Path::new("/src"),
+            Outcome::Skipped("no longer ignored".to_owned()),
+        )
+        .unwrap();
+
+        // A second run, after a line cut short by a crash
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"run\":")
+            .unwrap();
+        let mut log = AuditLog {
+            run: log.run + 1,
+            ..AuditLog::open_at(&path).unwrap()
+        };
+        log.record(&candidate("/src/c/target", 7), Path::new("/src"), Outcome::Removed)
+            .unwrap();
+
+        // Entries written before skips were recorded separately
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(
+                b"{\"run\":1,\"timestamp\":0,\"user\":\"me\",\"starting_dir\":\"/src\",\"path\":\"/src/e/target\",\
+                  \"bytes\":3,\"glob\":null,\"source\":null,\"error\":null}\n",
+            )
+            .unwrap();
+
+        let entries = load(&path).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[0].glob.as_deref(), Some("target/"));
+        assert_eq!(entries[2].skipped.as_deref(), Some("no longer ignored"));
+        assert_eq!(entries[4].skipped, None);
+        let runs = runs(&entries);
+        assert_eq!(runs.len(), 3);
+        assert_eq!(reclaimed(runs[0].iter().copied()), 100);
+        assert_eq!(reclaimed(&entries), 110);
+    }
+
+    #[test]
+    fn interleaved_runs() {
+        let entry = |run, pid, bytes| AuditEntry {
+            run,
+            pid,
+            timestamp: 0,
+            user: "me".to_owned(),
+            starting_dir: PathBuf::from("/src"),
+            path: PathBuf::from("/src/target"),
+            bytes,
+            glob: None,
+            source: None,
+            error: None,
+            skipped: None,
+        };
+
+        // Concurrent runs writing to the same log, two of them started in the same millisecond
+        let entries = vec![
+            entry(2, 7, 1),
+            entry(1, 8, 10),
+            entry(2, 9, 1000),
+            entry(2, 7, 2),
+            entry(1, 8, 20),
+            entry(3, 7, 100),
+        ];
+        let runs = runs(&entries);
+        let summary: Vec<_> = runs
+            .iter()
+            .map(|run| (run[0].run, run[0].pid, run.len(), reclaimed(run.iter().copied())))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(2, 7, 2, 3), (1, 8, 2, 30), (2, 9, 1, 1000), (3, 7, 1, 100)]
+        );
+    }
+}
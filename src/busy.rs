@@ -0,0 +1,255 @@
+use crate::projects::ProjectKind;
+use crate::scan::Candidate;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+// Lock files cargo holds with flock while building or updating its registry
+const CARGO_LOCKS: &[&str] = &[".cargo-lock", ".package-cache"];
+
+// Files cargo writes at the top of every target directory
+const CARGO_TARGET_MARKERS: &[&str] = &[".rustc_info.json", "CACHEDIR.TAG"];
+
+// Line declaring a lock file in a `.gitnuke`, such as `# lock: Intermediate/.build-lock`
+// While the lock file exists nothing below the `.gitnuke`'s directory is nuked. The directive is a
+// comment so git and the ignore patterns don't see it.
+const LOCK_DIRECTIVE: &str = "# lock:";
+
+// Why a candidate looks like it's being built
+#[derive(Debug, PartialEq, Eq)]
+pub enum BusyReason {
+    // Cargo lock file currently held
+    Locked(PathBuf),
+
+    // Path modified within `--busy-within`
+    Modified(PathBuf),
+
+    // Lock file declared in a `.gitnuke` exists
+    Declared { lock: PathBuf, gitnuke: PathBuf },
+}
+
+impl fmt::Display for BusyReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusyReason::Locked(path) => write!(f, "locked [{:?}]", path),
+            BusyReason::Modified(path) => write!(f, "recently modified [{:?}]", path),
+            BusyReason::Declared { lock, gitnuke } => write!(f, "lock [{:?}] declared in [{:?}] exists", lock, gitnuke),
+        }
+    }
+}
+
+// Detects candidates showing signs of an active build
+pub struct BusyCheck {
+    // Paths modified more recently than this are busy. None skips the check.
+    recent: Option<Duration>,
+
+    // Lock files declared by the `.gitnuke` in each directory
+    directives: Mutex<HashMap<PathBuf, Vec<PathBuf>>>,
+}
+
+impl BusyCheck {
+    pub fn new(recent: Option<Duration>) -> BusyCheck {
+        BusyCheck {
+            recent,
+            directives: Default::default(),
+        }
+    }
+
+    // First sign of activity in `candidate`, consulting `.gitnuke` files up to `starting_dir`
+    pub fn check(&self, candidate: &Candidate, starting_dir: &Path) -> Option<BusyReason> {
+        self.declared_lock(&candidate.path, starting_dir)
+            .or_else(|| {
+                // Only cargo target directories are probed, the probe reads two levels of directories
+                if !is_cargo_target(candidate) {
+                    return None;
+                }
+                held_cargo_lock(&candidate.path).map(BusyReason::Locked)
+            })
+            .or_else(|| {
+                let cutoff = SystemTime::now() - self.recent?;
+                modified_since(&candidate.path, cutoff).map(BusyReason::Modified)
+            })
+    }
+
+    fn declared_lock(&self, path: &Path, starting_dir: &Path) -> Option<BusyReason> {
+        let mut directives = self.directives.lock().unwrap();
+        for dir in path.ancestors().skip(1).take_while(|dir| dir.starts_with(starting_dir)) {
+            let locks = directives
+                .entry(dir.to_owned())
+                .or_insert_with(|| read_lock_directives(dir));
+            if let Some(lock) = locks.iter().find(|lock| lock.exists()) {
+                return Some(BusyReason::Declared {
+                    lock: lock.clone(),
+                    gitnuke: dir.join(".gitnuke"),
+                });
+            }
+        }
+        None
+    }
+}
+
+// Lock files declared by the `.gitnuke` in `dir`, if any
+fn read_lock_directives(dir: &Path) -> Vec<PathBuf> {
+    let content = fs::read_to_string(dir.join(".gitnuke")).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix(LOCK_DIRECTIVE))
+        .map(str::trim)
+        .filter(|lock| !lock.is_empty())
+        .map(|lock| dir.join(lock))
+        .collect()
+}
+
+// Whether `candidate` is a cargo target directory, detected by `--projects` or by the files cargo writes
+fn is_cargo_target(candidate: &Candidate) -> bool {
+    let is_project_output = candidate
+        .project
+        .as_ref()
+        .is_some_and(|project| project.kind == ProjectKind::Cargo);
+    is_project_output
+        || CARGO_TARGET_MARKERS
+            .iter()
+            .any(|name| candidate.path.join(name).is_file())
+}
+
+// Cargo lock held by another process, in `path` or up to two directories below it
+// That covers `target/debug/.cargo-lock` and `target/<triple>/debug/.cargo-lock`.
+fn held_cargo_lock(path: &Path) -> Option<PathBuf> {
+    let mut dirs = vec![path.to_owned()];
+    for depth in 0..3 {
+        let mut next = Vec::new();
+        for dir in dirs {
+            for name in CARGO_LOCKS {
+                let lock = dir.join(name);
+                if is_locked(&lock) {
+                    return Some(lock);
+                }
+            }
+            if depth < 2 {
+                let children = fs::read_dir(&dir).into_iter().flatten().flatten();
+                next.extend(
+                    children
+                        .filter(|child| child.file_type().is_ok_and(|t| t.is_dir()))
+                        .map(|child| child.path()),
+                );
+            }
+        }
+        dirs = next;
+    }
+    None
+}
+
+// Whether another process holds a lock on `path`
+// The probe takes the lock for an instant, which at worst makes a build wait that long.
+fn is_locked(path: &Path) -> bool {
+    match File::open(path) {
+        Ok(file) => matches!(file.try_lock(), Err(TryLockError::WouldBlock)),
+        Err(_) => false,
+    }
+}
+
+// First path in `path` modified after `cutoff`. Symlinks aren't followed.
+fn modified_since(path: &Path, cutoff: SystemTime) -> Option<PathBuf> {
+    let meta = fs::symlink_metadata(path).ok()?;
+    if meta.modified().is_ok_and(|mtime| mtime > cutoff) {
+        return Some(path.to_owned());
+    }
+    if meta.is_dir() {
+        for child in fs::read_dir(path).ok()?.flatten() {
+            if let Some(modified) = modified_since(&child.path(), cutoff) {
+                return Some(modified);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+
+    fn backdate(path: &Path) {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(1_000_000_000, 0)).unwrap();
+    }
+
+    #[test]
+    fn recently_modified() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("target");
+        fs::create_dir_all(target.join("debug")).unwrap();
+        fs::write(target.join("debug").join("app"), "").unwrap();
+        for path in [target.join("debug").join("app"), target.join("debug"), target.clone()] {
+            backdate(&path);
+        }
+
+        let check = BusyCheck::new(Some(Duration::from_secs(600)));
+        let candidate = Candidate::new(target.clone());
+        assert_eq!(check.check(&candidate, tempdir.path()), None);
+
+        fs::write(target.join("debug").join("app"), "new").unwrap();
+        assert_eq!(
+            check.check(&candidate, tempdir.path()),
+            Some(BusyReason::Modified(target.join("debug").join("app")))
+        );
+        assert_eq!(BusyCheck::new(None).check(&candidate, tempdir.path()), None);
+    }
+
+    #[test]
+    fn declared_locks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let project = tempdir.path().join("project");
+        let output = project.join("Intermediate");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(
+            project.join(".gitnuke"),
+            "!/Content\n# lock: Intermediate/.build-lock\n",
+        )
+        .unwrap();
+
+        let check = || BusyCheck::new(None).check(&Candidate::new(output.clone()), tempdir.path());
+        assert_eq!(check(), None);
+
+        fs::write(output.join(".build-lock"), "").unwrap();
+        assert_eq!(
+            check(),
+            Some(BusyReason::Declared {
+                lock: output.join(".build-lock"),
+                gitnuke: project.join(".gitnuke"),
+            })
+        );
+    }
+
+    #[test]
+    fn held_cargo_locks() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("target");
+        let lock_path = target
+            .join("x86_64-unknown-linux-gnu")
+            .join("debug")
+            .join(".cargo-lock");
+        fs::create_dir_all(lock_path.parent().unwrap()).unwrap();
+        fs::write(&lock_path, "").unwrap();
+
+        // An unheld lock file is left behind by every finished build
+        assert_eq!(held_cargo_lock(&target), None);
+
+        // Locks are per open file description, so this conflicts with the probe
+        let lock = File::open(&lock_path).unwrap();
+        lock.lock().unwrap();
+        assert_eq!(held_cargo_lock(&target), Some(lock_path.clone()));
+
+        // Only probed in cargo target directories
+        let check = BusyCheck::new(None);
+        let candidate = Candidate::new(target.clone());
+        assert_eq!(check.check(&candidate, tempdir.path()), None);
+        fs::write(target.join("CACHEDIR.TAG"), "").unwrap();
+        assert_eq!(
+            check.check(&candidate, tempdir.path()),
+            Some(BusyReason::Locked(lock_path))
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum CacheCommand {
+    #[structopt(about = "Deletes the scan cache")]
+    Clear,
+}
+
+// Paths modified this recently are not cached
+// Filesystem timestamps are coarse so a change made within the same tick would go unnoticed.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+// Contents of a single directory as of its modification time
+// A directory's mtime changes whenever an entry directly inside it is added, removed or renamed, but not
+// when a file inside it is modified in place.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirRecord {
+    mtime: u128,
+    pub dirs: Vec<String>,
+
+    // Names of files. None if only their total size was recorded.
+    pub files: Option<Vec<String>>,
+
+    pub num_files: u64,
+    pub files_size: u64,
+}
+
+// Patterns of a single ignore file as of its modification time and length
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct IgnoreRecord {
+    mtime: u128,
+    len: u64,
+    lines: Vec<String>,
+}
+
+// Everything saved between runs, keyed by path
+#[derive(Default, Serialize, Deserialize)]
+struct Records {
+    #[serde(default)]
+    dirs: HashMap<String, DirRecord>,
+    #[serde(default)]
+    ignores: HashMap<String, IgnoreRecord>,
+}
+
+// On-disk cache of directory listings and ignore file patterns, see `--no-cache`
+// Records are loaded once, consulted by every worker, and written back once the scan is done. Each
+// directory and ignore file is validated against its own modification time, so only files modified in
+// place go unnoticed. Whether a directory is a source control root is never cached.
+pub struct ScanCache {
+    path: Option<PathBuf>,
+    previous: Records,
+    current: Mutex<Records>,
+}
+
+impl ScanCache {
+    // Load the cache, or start from scratch if it's missing or unreadable
+    pub fn load() -> ScanCache {
+        let path = cache_path();
+        let previous = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        ScanCache {
+            path,
+            previous,
+            current: Default::default(),
+        }
+    }
+
+    // Cache that never hits and is never saved
+    pub fn disabled() -> ScanCache {
+        ScanCache {
+            path: None,
+            previous: Default::default(),
+            current: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    // Listing of `dir` from the last run, if its mtime hasn't changed since
+    // `meta` must be the metadata of `dir`. File names are only returned if `with_files` is set.
+    pub fn get_dir(&self, dir: &Path, meta: &Metadata, with_files: bool) -> Option<DirRecord> {
+        let key = dir.to_str()?;
+        let cached = self.previous.dirs.get(key)?;
+        if Some(cached.mtime) != trusted_mtime(meta) || (with_files && cached.files.is_none()) {
+            return None;
+        }
+        self.current.lock().unwrap().dirs.insert(key.to_owned(), cached.clone());
+        Some(cached.clone())
+    }
+
+    // Remember the listing of `dir`, which must have been read completely without errors
+    pub fn insert_dir(
+        &self,
+        dir: &Path,
+        meta: &Metadata,
+        dirs: Vec<String>,
+        files: Option<Vec<String>>,
+        num_files: u64,
+        files_size: u64,
+    ) {
+        if self.path.is_none() {
+            return;
+        }
+        if let (Some(key), Some(mtime)) = (dir.to_str(), trusted_mtime(meta)) {
+            let record = DirRecord {
+                mtime,
+                dirs,
+                files,
+                num_files,
+                files_size,
+            };
+            self.current.lock().unwrap().dirs.insert(key.to_owned(), record);
+        }
+    }
+
+    // Patterns of the ignore file at `path`, read from the last run if it's unchanged since
+    // `meta` must be the metadata of `path`. None if the file can't be read as text.
+    pub fn ignore_lines(&self, path: &Path, meta: &Metadata) -> Option<Vec<String>> {
+        let key = path.to_str();
+        let mtime = trusted_mtime(meta);
+        if let (Some(key), Some(mtime)) = (key, mtime) {
+            if let Some(cached) = self.previous.ignores.get(key) {
+                if cached.mtime == mtime && cached.len == meta.len() {
+                    self.current
+                        .lock()
+                        .unwrap()
+                        .ignores
+                        .insert(key.to_owned(), cached.clone());
+                    return Some(cached.lines.clone());
+                }
+            }
+        }
+
+        // Lines are split like git does, dropping a leading byte order mark
+        let text = fs::read_to_string(path).ok()?;
+        let lines: Vec<String> = text
+            .strip_prefix('\u{feff}')
+            .unwrap_or(&text)
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        if let (Some(key), Some(mtime), true) = (key, mtime, self.is_enabled()) {
+            let record = IgnoreRecord {
+                mtime,
+                len: meta.len(),
+                lines: lines.clone(),
+            };
+            self.current.lock().unwrap().ignores.insert(key.to_owned(), record);
+        }
+        Some(lines)
+    }
+
+    // Write records back to disk
+    // Records from outside `scanned_dirs` are kept. Records inside them that were not used are dropped,
+    // since the paths were nuked or are no longer walked.
+    pub fn save(self, scanned_dirs: &[PathBuf]) -> anyhow::Result<()> {
+        let path = match self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let outside = |key: &String| !scanned_dirs.iter().any(|dir| Path::new(key).starts_with(dir));
+        let mut records = self.current.into_inner().unwrap();
+        for (key, record) in self.previous.dirs {
+            if outside(&key) {
+                records.dirs.entry(key).or_insert(record);
+            }
+        }
+        for (key, record) in self.previous.ignores {
+            if outside(&key) {
+                records.ignores.entry(key).or_insert(record);
+            }
+        }
+
+        // Written next to the cache then renamed over it, so a crash or a concurrent run never leaves a
+        // truncated cache behind
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&temp_path, serde_json::to_vec(&records)?)
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&temp_path);
+            })
+            .with_context(|| format!("Failed to write scan cache [{}]", path.display()))?;
+        Ok(())
+    }
+}
+
+// Handle `cache` subcommands
+pub fn run(command: &CacheCommand) -> anyhow::Result<()> {
+    match command {
+        CacheCommand::Clear => {
+            let path = cache_path().ok_or_else(|| anyhow!("Failed to find cache directory"))?;
+            match fs::remove_file(&path) {
+                Ok(()) => println!("Cleared scan cache [{}]", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => println!("Scan cache is already empty"),
+                Err(e) => return Err(e).with_context(|| format!("Failed to delete [{}]", path.display())),
+            }
+            Ok(())
+        }
+    }
+}
+
+// The cache lives in `$FTS_GITIGNORE_NUKE_CACHE_DIR` if set, or else the platform's cache directory
+fn cache_path() -> Option<PathBuf> {
+    env::var_os("FTS_GITIGNORE_NUKE_CACHE_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("fts_gitignore_nuke")))
+        .map(|dir| dir.join("scan_cache.json"))
+}
+
+// Modification time in nanoseconds, unless it's too recent to trust
+fn trusted_mtime(meta: &Metadata) -> Option<u128> {
+    let mtime = meta.modified().ok()?;
+    let age = SystemTime::now().duration_since(mtime).ok()?;
+    if age < RACY_WINDOW {
+        return None;
+    }
+    mtime.duration_since(SystemTime::UNIX_EPOCH).ok().map(|d| d.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_at(path: &Path) -> ScanCache {
+        ScanCache {
+            path: Some(path.to_owned()),
+            ..ScanCache::disabled()
+        }
+    }
+
+    fn reload(path: &Path) -> ScanCache {
+        ScanCache {
+            previous: serde_json::from_slice(&fs::read(path).unwrap()).unwrap(),
+            ..cache_at(path)
+        }
+    }
+
+    fn backdate(path: &Path) {
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        filetime::set_file_mtime(path, old).unwrap();
+    }
+
+    #[test]
+    fn listing_is_cached_until_dir_changes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().join("target");
+        let cache_file = tempdir.path().join("cache.json");
+        fs::create_dir_all(&dir).unwrap();
+        backdate(&dir);
+
+        let cache = cache_at(&cache_file);
+        let meta = fs::metadata(&dir).unwrap();
+        assert_eq!(cache.get_dir(&dir, &meta, false), None);
+        cache.insert_dir(&dir, &meta, vec!["debug".to_owned()], None, 1, 5);
+        cache.save(std::slice::from_ref(&dir)).unwrap();
+
+        let cache = reload(&cache_file);
+        assert_eq!(cache.get_dir(&dir, &meta, false).map(|r| r.files_size), Some(5));
+
+        // File names weren't recorded
+        assert_eq!(cache.get_dir(&dir, &meta, true), None);
+        cache.save(std::slice::from_ref(&dir)).unwrap();
+
+        // Adding a file changes the directory's mtime
+        fs::write(dir.join("b.txt"), "!").unwrap();
+        let cache = reload(&cache_file);
+        assert_eq!(cache.get_dir(&dir, &fs::metadata(&dir).unwrap(), false), None);
+
+        // Nothing is left behind by the atomic write
+        let names: Vec<_> = fs::read_dir(tempdir.path())
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name())
+            .collect();
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn ignore_lines_are_cached_until_file_changes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_file = tempdir.path().join("cache.json");
+        let ignore_path = tempdir.path().join(".gitignore");
+        fs::write(&ignore_path, "\u{feff}target/\r\n*.log\n").unwrap();
+        backdate(&ignore_path);
+
+        let cache = cache_at(&cache_file);
+        let meta = fs::metadata(&ignore_path).unwrap();
+        assert_eq!(
+            cache.ignore_lines(&ignore_path, &meta).unwrap(),
+            vec!["target/", "*.log"]
+        );
+        cache.save(&[]).unwrap();
+
+        // Served from the cache while the file is unchanged
+        fs::write(&ignore_path, "\u{feff}others/\r\n*.log\n").unwrap();
+        backdate(&ignore_path);
+        let cache = reload(&cache_file);
+        let meta = fs::metadata(&ignore_path).unwrap();
+        assert_eq!(
+            cache.ignore_lines(&ignore_path, &meta).unwrap(),
+            vec!["target/", "*.log"]
+        );
+
+        // Any change to its length or mtime is noticed
+        fs::write(&ignore_path, "out/\n").unwrap();
+        let meta = fs::metadata(&ignore_path).unwrap();
+        assert_eq!(cache.ignore_lines(&ignore_path, &meta).unwrap(), vec!["out/"]);
+    }
+
+    #[test]
+    fn recent_dirs_are_not_cached() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_file = tempdir.path().join("cache.json");
+        let cache = cache_at(&cache_file);
+        let meta = fs::metadata(tempdir.path()).unwrap();
+        cache.insert_dir(tempdir.path(), &meta, Vec::new(), None, 0, 0);
+        assert!(cache.current.lock().unwrap().dirs.is_empty());
+    }
+
+    #[test]
+    fn unused_records_are_dropped() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let cache_file = tempdir.path().join("cache.json");
+        let record = DirRecord {
+            mtime: 0,
+            dirs: Vec::new(),
+            files: None,
+            num_files: 0,
+            files_size: 0,
+        };
+
+        let mut cache = cache_at(&cache_file);
+        cache.previous.dirs.insert("/scanned/gone".to_owned(), record.clone());
+        cache.previous.dirs.insert("/elsewhere".to_owned(), record);
+        cache.save(&[PathBuf::from("/scanned")]).unwrap();
+
+        let cache = reload(&cache_file);
+        let keys: Vec<_> = cache.previous.dirs.keys().collect();
+        assert_eq!(keys, vec!["/elsewhere"]);
+    }
+}
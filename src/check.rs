@@ -0,0 +1,395 @@
+use crate::cache::ScanCache;
+use crate::errors::ErrorReport;
+use crate::exit_code::ExitStatus;
+use crate::listing::{self, ListArgs, Others};
+use crate::scan::{self, Candidate, ScanArgs};
+use crate::units::{ByteSize, Units};
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct CheckArgs {
+    #[structopt(long, help = "Fail if total ignored bytes exceed this budget, such as 50GB")]
+    pub max_total_bytes: Option<ByteSize>,
+
+    #[structopt(long, help = "Fail if any single ignored path exceeds this size, such as 2GiB")]
+    pub max_candidate_bytes: Option<ByteSize>,
+
+    #[structopt(long, parse(from_os_str), help = "Write a report to this file. Use - for stdout")]
+    pub report: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "json",
+        possible_values = &["json", "junit"],
+        help = "Format of the report written by --report"
+    )]
+    pub report_format: ReportFormat,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Junit,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ReportFormat> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "junit" => Ok(ReportFormat::Junit),
+            _ => Err(anyhow!("Unknown report format [{}]", s)),
+        }
+    }
+}
+
+// A single ignored path that exceeded the per-candidate budget
+#[derive(Debug, Serialize)]
+pub struct Offender {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+// Result of comparing scan results against the budgets
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub passed: bool,
+    pub num_candidates: usize,
+    pub total_bytes: u64,
+    pub max_total_bytes: Option<u64>,
+    pub max_candidate_bytes: Option<u64>,
+    pub total_exceeded: bool,
+    pub num_offenders: usize,
+
+    // Errors hit while scanning. Any of them means the totals may be short, which fails the check.
+    pub num_scan_errors: usize,
+
+    // Ordered and limited by `--sort`, `--reverse` and `--top`
+    pub offenders: Vec<Offender>,
+
+    // Offenders collapsed by `--collapse-below`
+    pub other_offenders: Option<Others>,
+}
+
+impl CheckReport {
+    pub fn new(
+        candidates: &[Candidate],
+        max_total_bytes: Option<u64>,
+        max_candidate_bytes: Option<u64>,
+        num_scan_errors: usize,
+        list_args: &ListArgs,
+    ) -> CheckReport {
+        let total_bytes: u64 = candidates.iter().map(|c| c.size).sum();
+        let total_exceeded = max_total_bytes.is_some_and(|max| total_bytes > max);
+
+        // Offenders are listed exactly like candidates in a normal run
+        let offending: Vec<Candidate> = match max_candidate_bytes {
+            Some(max) => candidates.iter().filter(|c| c.size > max).cloned().collect(),
+            None => Default::default(),
+        };
+        let num_offenders = offending.len();
+        let offending = listing::top(listing::arrange(offending, list_args), list_args);
+        let (shown, other_offenders) = listing::collapse(&offending, list_args);
+        let offenders = shown
+            .into_iter()
+            .map(|c| Offender {
+                path: c.path.clone(),
+                bytes: c.size,
+            })
+            .collect();
+
+        CheckReport {
+            passed: !total_exceeded && num_offenders == 0 && num_scan_errors == 0,
+            num_candidates: candidates.len(),
+            total_bytes,
+            max_total_bytes,
+            max_candidate_bytes,
+            total_exceeded,
+            num_offenders,
+            num_scan_errors,
+            offenders,
+            other_offenders,
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    // One testcase for the total budget and one failing testcase per offender
+    // Offenders left out by `--top` or collapsed by `--collapse-below` still count as failures.
+    pub fn to_junit(&self) -> String {
+        const SUITE: &str = "fts_gitignore_nuke.check";
+        let mut cases = String::new();
+        let mut num_tests = 0;
+        let mut num_failures = 0;
+
+        if self.num_scan_errors > 0 {
+            let msg = format!(
+                "{} errors while scanning, totals may be incomplete",
+                self.num_scan_errors
+            );
+            let _ = writeln!(
+                cases,
+                "    <testcase classname=\"{}\" name=\"complete scan\">\n      <failure message=\"{}\"/>\n    </testcase>",
+                SUITE,
+                xml_escape(&msg)
+            );
+            num_tests += 1;
+            num_failures += 1;
+        }
+
+        if let Some(max) = self.max_total_bytes {
+            num_tests += 1;
+            num_failures += usize::from(self.total_exceeded);
+            let _ = write!(
+                cases,
+                "    <testcase classname=\"{}\" name=\"total ignored bytes\"",
+                SUITE
+            );
+            if self.total_exceeded {
+                let msg = format!("{} bytes exceeds budget of {} bytes", self.total_bytes, max);
+                let _ = writeln!(
+                    cases,
+                    ">\n      <failure message=\"{}\"/>\n    </testcase>",
+                    xml_escape(&msg)
+                );
+            } else {
+                let _ = writeln!(cases, "/>");
+            }
+        }
+
+        if let Some(max) = self.max_candidate_bytes {
+            num_tests += self.num_offenders.max(1);
+            num_failures += self.num_offenders;
+            if self.num_offenders == 0 {
+                let _ = writeln!(
+                    cases,
+                    "    <testcase classname=\"{}\" name=\"ignored path size\"/>",
+                    SUITE
+                );
+            }
+            for offender in &self.offenders {
+                let msg = format!("{} bytes exceeds budget of {} bytes", offender.bytes, max);
+                let _ = writeln!(
+                    cases,
+                    "    <testcase classname=\"{}\" name=\"{}\">\n      <failure message=\"{}\"/>\n    </testcase>",
+                    SUITE,
+                    xml_escape(&offender.path.to_string_lossy()),
+                    xml_escape(&msg)
+                );
+            }
+            if let Some(others) = &self.other_offenders {
+                let _ = writeln!(
+                    cases,
+                    "    <testcase classname=\"{}\" name=\"{} other offenders\">\n      <failure message=\"{} bytes exceed budget of {} bytes\"/>\n    </testcase>",
+                    SUITE, others.count, others.bytes, max
+                );
+            }
+        }
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n{}  </testsuite>\n</testsuites>\n",
+            SUITE, num_tests, num_failures, cases
+        )
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Scan then compare results against the requested budgets
+pub fn run(
+    args: &CheckArgs,
+    scan_args: &ScanArgs,
+    list_args: &ListArgs,
+    print_errors: bool,
+    units: Units,
+) -> anyhow::Result<ExitStatus> {
+    if args.max_total_bytes.is_none() && args.max_candidate_bytes.is_none() {
+        return Err(anyhow!("check requires --max-total-bytes and/or --max-candidate-bytes"));
+    }
+
+    // Human readable output goes to stderr when the report is written to stdout, so it can be parsed
+    let report_to_stdout = args.report.as_ref().is_some_and(|path| path.as_os_str() == "-");
+    if report_to_stdout && scan_args.print_glob_matches {
+        return Err(anyhow!(
+            "--print-glob-matches can't be combined with --report -, which writes to stdout"
+        ));
+    }
+    let mut out: Box<dyn std::io::Write> = if report_to_stdout {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+
+    let error_report = ErrorReport::new();
+    let starting_dirs = scan::starting_dirs(scan_args)?;
+    writeln!(out, "🔍 scanning for targets from {:?}", starting_dirs)?;
+
+    // Budgets are checked against what's on disk now, so the cache is never used
+    let candidates = scan::scan(scan_args, &starting_dirs, ScanCache::disabled(), &error_report)?.candidates;
+    let report = CheckReport::new(
+        &candidates,
+        args.max_total_bytes.map(|b| b.0),
+        args.max_candidate_bytes.map(|b| b.0),
+        error_report.num_errors(),
+        list_args,
+    );
+
+    // Human readable summary
+    if report.num_scan_errors > 0 {
+        writeln!(
+            out,
+            "❌ {} errors while scanning, totals may be incomplete",
+            report.num_scan_errors
+        )?;
+    }
+    if report.total_exceeded {
+        writeln!(
+            out,
+            "❌ Total ignored bytes {} exceeds budget of {}",
+            units.format(report.total_bytes),
+            units.format(report.max_total_bytes.unwrap_or_default())
+        )?;
+    }
+    for offender in &report.offenders {
+        writeln!(out, "❌ {:10} {:?}", units.format(offender.bytes), offender.path)?;
+    }
+    if let Some(others) = &report.other_offenders {
+        writeln!(
+            out,
+            "❌ {:10} ...and {} other offenders",
+            units.format(others.bytes),
+            others.count
+        )?;
+    }
+    if report.passed {
+        writeln!(
+            out,
+            "✅ {} in {} ignored paths is within budget",
+            units.format(report.total_bytes),
+            report.num_candidates
+        )?;
+    }
+
+    // Machine readable report
+    if let Some(report_path) = &args.report {
+        let contents = match args.report_format {
+            ReportFormat::Json => report.to_json()?,
+            ReportFormat::Junit => report.to_junit(),
+        };
+        if report_to_stdout {
+            std::io::stdout().write_all(contents.as_bytes())?;
+        } else {
+            fs::write(report_path, contents)
+                .with_context(|| format!("Failed to write report [{}]", report_path.display()))?;
+        }
+    }
+
+    // An incomplete scan can't vouch for the budget either way
+    error_report.write_summary(&mut out, print_errors)?;
+    if report.num_scan_errors > 0 {
+        Ok(ExitStatus::Error)
+    } else if report.passed {
+        Ok(error_report.exit_status(ExitStatus::Success))
+    } else {
+        Ok(error_report.exit_status(ExitStatus::BudgetExceeded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, size: u64) -> Candidate {
+        Candidate {
+            size,
+            ..Candidate::new(PathBuf::from(path))
+        }
+    }
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            candidate("a/target", 500),
+            candidate("b/<node_modules>", 300),
+            candidate("c/build", 100),
+        ]
+    }
+
+    #[test]
+    fn within_budget() {
+        let report = CheckReport::new(&candidates(), Some(900), Some(500), 0, &ListArgs::default());
+        assert!(report.passed);
+        assert!(!report.total_exceeded);
+        assert!(report.offenders.is_empty());
+        assert_eq!(report.total_bytes, 900);
+    }
+
+    #[test]
+    fn exceeds_budget() {
+        let report = CheckReport::new(&candidates(), Some(800), Some(200), 0, &ListArgs::default());
+        assert!(!report.passed);
+        assert!(report.total_exceeded);
+        let offenders: Vec<_> = report.offenders.iter().map(|o| o.bytes).collect();
+        assert_eq!(offenders, vec![500, 300]);
+    }
+
+    #[test]
+    fn offenders_are_listed_like_candidates() {
+        let args = ListArgs {
+            reverse: true,
+            top: Some(1),
+            ..Default::default()
+        };
+        let report = CheckReport::new(&candidates(), None, Some(200), 0, &args);
+        assert!(!report.passed);
+        assert_eq!(report.num_offenders, 2);
+        let offenders: Vec<_> = report.offenders.iter().map(|o| o.bytes).collect();
+        assert_eq!(offenders, vec![300]);
+    }
+
+    #[test]
+    fn junit_report() {
+        let report = CheckReport::new(&candidates(), Some(800), Some(400), 0, &ListArgs::default());
+        let xml = report.to_junit();
+        assert!(xml.contains("tests=\"2\" failures=\"2\""));
+        assert!(xml.contains("name=\"a/target\""));
+
+        let report = CheckReport::new(&candidates(), None, Some(250), 0, &ListArgs::default());
+        let xml = report.to_junit();
+        assert!(xml.contains("name=\"b/&lt;node_modules&gt;\""));
+
+        // Offenders beyond `--top` still fail
+        let args = ListArgs {
+            top: Some(1),
+            ..Default::default()
+        };
+        let xml = CheckReport::new(&candidates(), None, Some(200), 0, &args).to_junit();
+        assert!(xml.contains("tests=\"2\" failures=\"2\""), "{}", xml);
+        assert!(!xml.contains("name=\"b/&lt;node_modules&gt;\""));
+    }
+
+    #[test]
+    fn scan_errors_fail_the_check() {
+        let report = CheckReport::new(&candidates(), Some(900), None, 2, &ListArgs::default());
+        assert!(!report.passed);
+        assert!(!report.total_exceeded);
+        let xml = report.to_junit();
+        assert!(xml.contains("tests=\"2\" failures=\"1\""), "{}", xml);
+        assert!(xml.contains("2 errors while scanning"));
+    }
+}
@@ -0,0 +1,599 @@
+use crate::errors::{Operation, PathError};
+use std::path::{Path, PathBuf};
+
+// Remove `path` and everything inside it without ever leaving it
+// Symlinks are removed, never followed. Directories on another filesystem, such as a mount point inside
+// a build directory, are left alone. Directories missing read, write or search permission are granted
+// it first. Every path that can't be removed is reported, and everything else is still removed.
+// A `path` that's already gone is not an error.
+pub fn remove(path: &Path) -> Vec<PathError> {
+    let mut errors = Vec::new();
+    imp::remove(path, &mut errors);
+    errors
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::ffi::{CStr, CString, OsStr};
+    use std::io;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+    // Directories are walked through file descriptors, relative to their parent, so a path component
+    // swapped for a symlink mid-walk can't redirect the walk elsewhere
+    pub fn remove(path: &Path, errors: &mut Vec<PathError>) {
+        let (parent, name) = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => {
+                errors.push(PathError::io(Operation::RemoveDir, path, invalid()));
+                return;
+            }
+        };
+        let parent_dir = match open_dir(parent) {
+            Ok(fd) => fd,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => return errors.push(PathError::io(Operation::OpenDir, parent, e)),
+        };
+        let parent_dev = match fstat(&parent_dir) {
+            Ok(st) => st.st_dev,
+            Err(e) => return errors.push(PathError::io(Operation::Stat, parent, e)),
+        };
+        let name = match cstring(name) {
+            Ok(name) => name,
+            Err(e) => return errors.push(PathError::io(Operation::RemoveDir, path, e)),
+        };
+        match stat_at(parent_dir.as_raw_fd(), &name) {
+            // A candidate that is itself a mount point is refused like any other
+            Ok(st) if is_dir(&st) => {
+                remove_dir_at(parent_dir.as_raw_fd(), &name, &st, parent_dev, path, errors);
+            }
+            Ok(_) => {
+                if let Err(e) = unlink_at(parent_dir.as_raw_fd(), &name, 0) {
+                    errors.push(PathError::io(Operation::RemoveFile, path, e));
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => errors.push(PathError::io(Operation::Metadata, path, e)),
+        }
+    }
+
+    // Directory being emptied, along with what is left to remove in it
+    struct Frame {
+        // Name in its parent directory
+        name: CString,
+
+        // Identity when it was opened, so it can be found again through `..` of a child
+        st: libc::stat,
+
+        path: PathBuf,
+        names: Vec<CString>,
+
+        // Whether everything inside was removed so far
+        emptied: bool,
+    }
+
+    // Empty and remove directory `name` in `parent`, staying on device `dev`
+    // Returns whether it was removed. Failures are pushed to `errors`.
+    // The walk keeps an explicit stack instead of recursing, so deep trees can't run out of stack. Only the
+    // directory being emptied is open. Its parent is opened again through `..` when the walk returns to it,
+    // and must still be the same directory.
+    fn remove_dir_at(
+        parent: RawFd,
+        name: &CStr,
+        st: &libc::stat,
+        dev: libc::dev_t,
+        path: &Path,
+        errors: &mut Vec<PathError>,
+    ) -> bool {
+        let (mut dir, mut stack) = match open_frame(parent, name, st, dev, path, errors) {
+            Some((dir, frame)) => (dir, vec![frame]),
+            None => return false,
+        };
+
+        loop {
+            let frame = stack.last_mut().unwrap();
+            if let Some(child) = frame.names.pop() {
+                let child_path = frame.path.join(OsStr::from_bytes(child.to_bytes()));
+                match stat_at(dir.as_raw_fd(), &child) {
+                    // Descend, closing this directory until the walk returns to it
+                    Ok(child_st) if is_dir(&child_st) => {
+                        match open_frame(dir.as_raw_fd(), &child, &child_st, dev, &child_path, errors) {
+                            Some((child_dir, child_frame)) => {
+                                dir = child_dir;
+                                stack.push(child_frame);
+                            }
+                            None => frame.emptied = false,
+                        }
+                    }
+                    Ok(_) => {
+                        if let Err(e) = unlink_at(dir.as_raw_fd(), &child, 0) {
+                            errors.push(PathError::io(Operation::RemoveFile, &child_path, e));
+                            frame.emptied = false;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    Err(e) => {
+                        errors.push(PathError::io(Operation::Stat, &child_path, e));
+                        frame.emptied = false;
+                    }
+                }
+                continue;
+            }
+
+            // Every entry was handled, so return to the parent and remove this directory from it
+            let done = stack.pop().unwrap();
+            let parent_frame = match stack.last_mut() {
+                Some(parent_frame) => parent_frame,
+                // The candidate itself, removed through the parent it was found in
+                None => {
+                    drop(dir);
+                    if !done.emptied {
+                        return false;
+                    }
+                    return match unlink_at(parent, name, libc::AT_REMOVEDIR) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            errors.push(PathError::io(Operation::RemoveDir, path, e));
+                            false
+                        }
+                    };
+                }
+            };
+            dir = match reopen_parent(&dir, &parent_frame.st) {
+                Ok(parent_dir) => parent_dir,
+                Err(e) => {
+                    errors.push(PathError::io(Operation::OpenDir, &parent_frame.path, e));
+                    return false;
+                }
+            };
+
+            // Whatever is left inside was already reported. Removing the directory would only fail again.
+            if !done.emptied {
+                parent_frame.emptied = false;
+            } else if let Err(e) = unlink_at(dir.as_raw_fd(), &done.name, libc::AT_REMOVEDIR) {
+                errors.push(PathError::io(Operation::RemoveDir, &done.path, e));
+                parent_frame.emptied = false;
+            }
+        }
+    }
+
+    // Open directory `name` in `parent` and list it, staying on device `dev`
+    // Returns None if it can't be emptied. Failures are pushed to `errors`.
+    fn open_frame(
+        parent: RawFd,
+        name: &CStr,
+        st: &libc::stat,
+        dev: libc::dev_t,
+        path: &Path,
+        errors: &mut Vec<PathError>,
+    ) -> Option<(OwnedFd, Frame)> {
+        // Mount points have the device of the mounted filesystem
+        if st.st_dev != dev {
+            let e = io::Error::new(io::ErrorKind::CrossesDevices, "another filesystem is mounted here");
+            errors.push(PathError::io(Operation::RemoveDir, path, e));
+            return None;
+        }
+
+        // Without read and search permission it can't even be opened. Grant them through the parent.
+        // The chmod must not follow a symlink swapped in since the stat. Where that can't be guaranteed
+        // the chmod fails instead.
+        let dir = match open_dir_at(parent, name) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                let mode = (st.st_mode & 0o7777) | 0o700;
+                check(unsafe { libc::fchmodat(parent, name.as_ptr(), mode, libc::AT_SYMLINK_NOFOLLOW) })
+                    .map_err(|e| match e.raw_os_error() {
+                        Some(code) if code == libc::ENOTSUP || code == libc::EOPNOTSUPP => {
+                            io::Error::other("can't change permissions without following symlinks")
+                        }
+                        _ => e,
+                    })
+                    .map_err(|e| PathError::io(Operation::Chmod, path, e))
+                    .and_then(|_| open_dir_at(parent, name).map_err(|e| PathError::io(Operation::OpenDir, path, e)))
+            }
+            opened => opened.map_err(|e| PathError::io(Operation::OpenDir, path, e)),
+        };
+        let dir = match dir {
+            Ok(dir) => dir,
+            Err(e) => {
+                errors.push(e);
+                return None;
+            }
+        };
+
+        // Swapped for something else since it was stat'd
+        let opened = match fstat(&dir) {
+            Ok(opened) if opened.st_dev == st.st_dev && opened.st_ino == st.st_ino => opened,
+            Ok(_) => {
+                let e = io::Error::other("replaced while being removed");
+                errors.push(PathError::io(Operation::RemoveDir, path, e));
+                return None;
+            }
+            Err(e) => {
+                errors.push(PathError::io(Operation::Stat, path, e));
+                return None;
+            }
+        };
+
+        // Entries can only be removed with write permission
+        if opened.st_mode & 0o700 != 0o700 {
+            let mode = (opened.st_mode & 0o7777) | 0o700;
+            if let Err(e) = check(unsafe { libc::fchmod(dir.as_raw_fd(), mode) }) {
+                errors.push(PathError::io(Operation::Chmod, path, e));
+                return None;
+            }
+        }
+
+        let names = match list(&dir) {
+            Ok(names) => names,
+            Err(e) => {
+                errors.push(PathError::io(Operation::OpenDir, path, e));
+                return None;
+            }
+        };
+
+        let frame = Frame {
+            name: name.to_owned(),
+            st: opened,
+            path: path.to_owned(),
+            names,
+            emptied: true,
+        };
+        Some((dir, frame))
+    }
+
+    // Parent of `dir`, which must still be the directory identified by `expected`
+    // A directory moved elsewhere mid-walk has a different parent, which is never touched.
+    fn reopen_parent(dir: &OwnedFd, expected: &libc::stat) -> io::Result<OwnedFd> {
+        let parent = open_dir_at(dir.as_raw_fd(), CStr::from_bytes_with_nul(b"..\0").unwrap())?;
+        let st = fstat(&parent)?;
+        if st.st_dev != expected.st_dev || st.st_ino != expected.st_ino {
+            return Err(io::Error::other("moved while being removed"));
+        }
+        Ok(parent)
+    }
+
+    // Names in `dir`, except `.` and `..`
+    fn list(dir: &OwnedFd) -> io::Result<Vec<CString>> {
+        // The stream takes ownership of the descriptor it's given
+        let fd = check(unsafe { libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0) })?;
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let e = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+
+        // readdir returns null both at the end and on failure, told apart by errno
+        let mut names = Vec::new();
+        let result = loop {
+            let checked = clear_errno();
+            let entry = unsafe { libc::readdir(stream) };
+            if entry.is_null() {
+                let e = io::Error::last_os_error();
+                break match e.raw_os_error() {
+                    Some(code) if checked && code != 0 => Err(e),
+                    _ => Ok(names),
+                };
+            }
+            let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+            if name.to_bytes() != b"." && name.to_bytes() != b".." {
+                names.push(name.to_owned());
+            }
+        };
+        unsafe { libc::closedir(stream) };
+        result
+    }
+
+    // Reset errno before a call that only reports failure through it
+    // Returns false where errno can't be reset, so such failures can't be detected.
+    fn clear_errno() -> bool {
+        #[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "redox"))]
+        unsafe {
+            *libc::__errno_location() = 0;
+            return true;
+        }
+        #[cfg(any(
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "dragonfly"
+        ))]
+        unsafe {
+            *libc::__error() = 0;
+            return true;
+        }
+        #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+        unsafe {
+            *libc::__errno() = 0;
+            return true;
+        }
+        #[allow(unreachable_code)]
+        false
+    }
+
+    fn open_dir(path: &Path) -> io::Result<OwnedFd> {
+        let path = cstring(path.as_os_str())?;
+        open_dir_flags(libc::AT_FDCWD, &path, 0)
+    }
+
+    fn open_dir_at(parent: RawFd, name: &CStr) -> io::Result<OwnedFd> {
+        open_dir_flags(parent, name, libc::O_NOFOLLOW)
+    }
+
+    fn open_dir_flags(parent: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<OwnedFd> {
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC | flags;
+        let fd = check(unsafe { libc::openat(parent, name.as_ptr(), flags) })?;
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn stat_at(parent: RawFd, name: &CStr) -> io::Result<libc::stat> {
+        let mut st = MaybeUninit::<libc::stat>::uninit();
+        check(unsafe { libc::fstatat(parent, name.as_ptr(), st.as_mut_ptr(), libc::AT_SYMLINK_NOFOLLOW) })?;
+        Ok(unsafe { st.assume_init() })
+    }
+
+    fn fstat(dir: &OwnedFd) -> io::Result<libc::stat> {
+        let mut st = MaybeUninit::<libc::stat>::uninit();
+        check(unsafe { libc::fstat(dir.as_raw_fd(), st.as_mut_ptr()) })?;
+        Ok(unsafe { st.assume_init() })
+    }
+
+    fn unlink_at(parent: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<()> {
+        check(unsafe { libc::unlinkat(parent, name.as_ptr(), flags) }).map(drop)
+    }
+
+    fn is_dir(st: &libc::stat) -> bool {
+        st.st_mode & libc::S_IFMT == libc::S_IFDIR
+    }
+
+    fn cstring(s: &OsStr) -> io::Result<CString> {
+        CString::new(s.as_bytes()).map_err(|_| invalid())
+    }
+
+    fn invalid() -> io::Error {
+        io::Error::from(io::ErrorKind::InvalidInput)
+    }
+
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::*;
+    use std::fs;
+
+    // Without directory handles a path swapped mid-walk can't be guarded against, and mount points
+    // aren't detected. Symlinks and junctions are still removed rather than followed.
+    pub fn remove(path: &Path, errors: &mut Vec<PathError>) {
+        match fs::symlink_metadata(path) {
+            Ok(meta) => {
+                remove_tree(path, &meta, errors);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => errors.push(PathError::io(Operation::Metadata, path, e)),
+        }
+    }
+
+    // Returns whether `path` was removed
+    fn remove_tree(path: &Path, meta: &fs::Metadata, errors: &mut Vec<PathError>) -> bool {
+        // Read-only files and directories can't be removed on Windows
+        // Symlinks are left as they are, setting their permissions would change the target's.
+        if meta.permissions().readonly() && !meta.file_type().is_symlink() {
+            let mut permissions = meta.permissions();
+            #[allow(clippy::permissions_set_readonly_false)]
+            permissions.set_readonly(false);
+            if let Err(e) = fs::set_permissions(path, permissions) {
+                errors.push(PathError::io(Operation::Chmod, path, e));
+                return false;
+            }
+        }
+
+        if meta.file_type().is_symlink() || !meta.is_dir() {
+            // Symlinks to directories are removed as directories on Windows
+            let result = fs::remove_file(path).or_else(|e| if meta.is_dir() { fs::remove_dir(path) } else { Err(e) });
+            return match result {
+                Ok(()) => true,
+                Err(e) => {
+                    errors.push(PathError::io(Operation::RemoveFile, path, e));
+                    false
+                }
+            };
+        }
+
+        let entries = match fs::read_dir(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(PathError::io(Operation::OpenDir, path, e));
+                return false;
+            }
+        };
+        let mut emptied = true;
+        for entry in entries {
+            let child = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    errors.push(PathError::io(Operation::OpenDir, path, e));
+                    emptied = false;
+                    continue;
+                }
+            };
+            emptied &= match fs::symlink_metadata(&child) {
+                Ok(child_meta) => remove_tree(&child, &child_meta, errors),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+                Err(e) => {
+                    errors.push(PathError::io(Operation::Stat, &child, e));
+                    false
+                }
+            };
+        }
+
+        // Whatever is left inside was already reported. Removing this directory would only fail again.
+        if !emptied {
+            return false;
+        }
+        match fs::remove_dir(path) {
+            Ok(()) => true,
+            Err(e) => {
+                errors.push(PathError::io(Operation::RemoveDir, path, e));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::fs;
+    use std::mem::MaybeUninit;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    #[test]
+    fn symlinks_are_not_followed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        let precious = root.join("precious");
+        let target = root.join("target");
+        fs::create_dir_all(precious.join("keep")).unwrap();
+        fs::write(precious.join("keep.txt"), "keep").unwrap();
+        fs::create_dir_all(target.join("debug")).unwrap();
+        symlink(&precious, target.join("debug").join("dir_link")).unwrap();
+        symlink(precious.join("keep.txt"), target.join("file_link")).unwrap();
+        symlink(root.join("missing"), target.join("dangling")).unwrap();
+
+        assert!(remove(&target).is_empty());
+        assert!(!target.exists());
+        assert!(precious.join("keep").exists());
+        assert!(precious.join("keep.txt").exists());
+
+        // A candidate that is itself a symlink
+        symlink(&precious, &target).unwrap();
+        assert!(remove(&target).is_empty());
+        assert!(fs::symlink_metadata(&target).is_err());
+        assert!(precious.join("keep.txt").exists());
+    }
+
+    #[test]
+    fn read_only_entries_are_removed() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("target");
+        let locked = target.join("locked");
+        let sealed = target.join("sealed");
+        fs::create_dir_all(locked.join("inner")).unwrap();
+        fs::create_dir_all(&sealed).unwrap();
+        fs::write(locked.join("inner").join("file.o"), "").unwrap();
+        fs::write(sealed.join("file.o"), "").unwrap();
+
+        let set_mode = |path: &Path, mode| fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        set_mode(&locked.join("inner").join("file.o"), 0o400);
+        set_mode(&locked.join("inner"), 0o500);
+        set_mode(&locked, 0o500);
+        set_mode(&sealed, 0o000);
+
+        assert!(remove(&target).is_empty());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn deep_trees_are_removed() {
+        // Deeper than the limit on open descriptors below, and too long for a path, so built relative to fds
+        const DEPTH: usize = 1000;
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("target");
+        fs::create_dir(&target).unwrap();
+        let name = CString::new("nested_directory").unwrap();
+        let mut dir = fs::File::open(&target).unwrap();
+        for _ in 0..DEPTH {
+            assert_eq!(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o755) }, 0);
+            let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY) };
+            assert!(fd >= 0);
+            dir = unsafe { fs::File::from_raw_fd(fd) };
+        }
+        drop(dir);
+
+        let mut original = MaybeUninit::<libc::rlimit>::uninit();
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, original.as_mut_ptr()) },
+            0
+        );
+        let original = unsafe { original.assume_init() };
+        let lowered = libc::rlimit {
+            rlim_cur: original.rlim_cur.min(256),
+            ..original
+        };
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &lowered) }, 0);
+        let errors = remove(&target);
+        assert_eq!(unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &original) }, 0);
+
+        assert!(errors.is_empty(), "{:?}", errors.first().map(ToString::to_string));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn files_and_missing_paths() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = tempdir.path().join("app.log");
+        fs::write(&file, "").unwrap();
+        assert!(remove(&file).is_empty());
+        assert!(!file.exists());
+
+        // Already gone, such as removed by a build between the check and the removal
+        assert!(remove(&file).is_empty());
+        assert!(remove(&tempdir.path().join("missing").join("target")).is_empty());
+    }
+
+    // Mounting needs root, so this only runs as root where mounting is permitted
+    #[test]
+    fn mount_points_are_not_crossed() {
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+        let tempdir = tempfile::tempdir().unwrap();
+        let target = tempdir.path().join("target");
+        let nested = target.join("mnt");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(target.join("app"), "").unwrap();
+
+        let mount = |path: &Path| {
+            std::process::Command::new("mount")
+                .args(["-t", "tmpfs", "none"])
+                .arg(path)
+                .status()
+                .is_ok_and(|status| status.success())
+        };
+        let unmount = |path: &Path| {
+            std::process::Command::new("umount").arg(path).status().unwrap();
+        };
+        if !mount(&nested) {
+            return;
+        }
+
+        // Mounted inside the candidate
+        fs::write(nested.join("keep"), "").unwrap();
+        let errors = remove(&target);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path.as_deref(), Some(nested.as_path()));
+        assert!(nested.join("keep").exists());
+        assert!(!target.join("app").exists());
+        unmount(&nested);
+
+        // The candidate itself is a mount point
+        assert!(mount(&target));
+        fs::write(target.join("keep"), "").unwrap();
+        let errors = remove(&target);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path.as_deref(), Some(target.as_path()));
+        assert!(target.join("keep").exists());
+        unmount(&target);
+    }
+}
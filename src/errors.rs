@@ -0,0 +1,259 @@
+use crate::exit_code::ExitStatus;
+use itertools::Itertools;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Filesystem operation that produced an error
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operation {
+    ReadDir,
+    Metadata,
+    RemoveFile,
+    RemoveDir,
+
+    // Steps of removing a directory tree, reported as deletion failures
+    OpenDir,
+    Stat,
+    Chmod,
+    Job,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operation::ReadDir => "fs::read_dir",
+            Operation::Metadata => "fs::metadata",
+            Operation::RemoveFile => "unlink",
+            Operation::RemoveDir => "rmdir",
+            Operation::OpenDir => "opendir",
+            Operation::Stat => "lstat",
+            Operation::Chmod => "chmod",
+            Operation::Job => "job",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Broad category of an error, used to summarise errors at the end of a run
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorKind {
+    PermissionDenied,
+    Vanished,
+    MetadataFailed,
+    ReadDirFailed,
+    DeleteFailed,
+    Panicked,
+}
+
+impl ErrorKind {
+    // Deletion failures always count as such, regardless of the underlying io error
+    fn classify(op: Operation, err: &io::Error) -> ErrorKind {
+        match op {
+            Operation::RemoveFile | Operation::RemoveDir | Operation::OpenDir | Operation::Stat | Operation::Chmod => {
+                ErrorKind::DeleteFailed
+            }
+            Operation::Job => ErrorKind::Panicked,
+            _ => match err.kind() {
+                io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+                io::ErrorKind::NotFound => ErrorKind::Vanished,
+                _ if op == Operation::ReadDir => ErrorKind::ReadDirFailed,
+                _ => ErrorKind::MetadataFailed,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::Vanished => "vanished",
+            ErrorKind::MetadataFailed => "metadata failed",
+            ErrorKind::ReadDirFailed => "read_dir failed",
+            ErrorKind::DeleteFailed => "delete failed",
+            ErrorKind::Panicked => "panicked",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Single error encountered while scanning or deleting
+#[derive(Debug)]
+pub struct PathError {
+    pub path: Option<PathBuf>,
+    pub op: Operation,
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl PathError {
+    pub fn io(op: Operation, path: &Path, err: io::Error) -> PathError {
+        PathError {
+            path: Some(path.to_owned()),
+            op,
+            kind: ErrorKind::classify(op, &err),
+            message: err.to_string(),
+        }
+    }
+
+    pub fn panic(message: String) -> PathError {
+        PathError {
+            path: None,
+            op: Operation::Job,
+            kind: ErrorKind::Panicked,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} {}: {}", self.op, path.display(), self.message),
+            None => write!(f, "{}: {}", self.op, self.message),
+        }
+    }
+}
+
+// Thread-safe collection of every error hit during a run
+#[derive(Default)]
+pub struct ErrorReport {
+    errors: Mutex<Vec<PathError>>,
+}
+
+impl ErrorReport {
+    pub fn new() -> ErrorReport {
+        Default::default()
+    }
+
+    pub fn record(&self, error: PathError) {
+        self.errors.lock().unwrap().push(error);
+    }
+
+    // Number of errors of any kind
+    pub fn num_errors(&self) -> usize {
+        self.errors.lock().unwrap().len()
+    }
+
+    pub fn count(&self, kind: ErrorKind) -> usize {
+        self.errors.lock().unwrap().iter().filter(|e| e.kind == kind).count()
+    }
+
+    // Status to exit with instead of `status`, given the errors recorded
+    // Panicked workers leave totals and candidates incomplete, so the run failed whatever it found.
+    pub fn exit_status(&self, status: ExitStatus) -> ExitStatus {
+        if self.count(ErrorKind::Panicked) > 0 {
+            ExitStatus::Error
+        } else {
+            status
+        }
+    }
+
+    // Print a summary of error counts by kind
+    // Individual errors are printed if `verbose` is set. Deletion failures and panics are always printed.
+    pub fn print_summary(&self, verbose: bool) {
+        let _ = self.write_summary(&mut io::stdout(), verbose);
+    }
+
+    // Write the summary printed by `print_summary` to `out`
+    pub fn write_summary(&self, out: &mut dyn io::Write, verbose: bool) -> io::Result<()> {
+        let errors = self.errors.lock().unwrap();
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "\n⚠️ {} errors encountered", errors.len())?;
+        for (kind, group) in &errors.iter().sorted_by_key(|e| e.kind).group_by(|e| e.kind) {
+            writeln!(out, "  {:18} {}", kind.to_string(), group.count())?;
+        }
+
+        for error in errors.iter() {
+            if verbose || matches!(error.kind, ErrorKind::DeleteFailed | ErrorKind::Panicked) {
+                writeln!(out, "Error: [{}]", error)?;
+            }
+        }
+
+        if !verbose {
+            writeln!(out, "Run with --print-errors to see every error.")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_errors() {
+        let path = Path::new("foo");
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        let missing = || io::Error::from(io::ErrorKind::NotFound);
+        let other = || io::Error::other("oops");
+
+        assert_eq!(
+            PathError::io(Operation::ReadDir, path, denied()).kind,
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            PathError::io(Operation::Metadata, path, missing()).kind,
+            ErrorKind::Vanished
+        );
+        assert_eq!(
+            PathError::io(Operation::ReadDir, path, other()).kind,
+            ErrorKind::ReadDirFailed
+        );
+        assert_eq!(
+            PathError::io(Operation::Metadata, path, other()).kind,
+            ErrorKind::MetadataFailed
+        );
+        assert_eq!(
+            PathError::io(Operation::RemoveDir, path, denied()).kind,
+            ErrorKind::DeleteFailed
+        );
+        assert_eq!(
+            PathError::io(Operation::OpenDir, path, denied()).kind,
+            ErrorKind::DeleteFailed
+        );
+    }
+
+    #[test]
+    fn count_by_kind() {
+        let report = ErrorReport::new();
+        let path = Path::new("foo");
+        report.record(PathError::io(Operation::Metadata, path, io::ErrorKind::NotFound.into()));
+        report.record(PathError::io(Operation::Metadata, path, io::ErrorKind::NotFound.into()));
+        report.record(PathError::io(
+            Operation::RemoveFile,
+            path,
+            io::ErrorKind::NotFound.into(),
+        ));
+        report.record(PathError::panic("boom".to_owned()));
+
+        assert_eq!(report.count(ErrorKind::Vanished), 2);
+        assert_eq!(report.count(ErrorKind::DeleteFailed), 1);
+        assert_eq!(report.count(ErrorKind::Panicked), 1);
+        assert_eq!(report.count(ErrorKind::PermissionDenied), 0);
+    }
+
+    #[test]
+    fn panics_fail_the_run() {
+        let report = ErrorReport::new();
+        report.record(PathError::io(
+            Operation::ReadDir,
+            Path::new("foo"),
+            io::ErrorKind::NotFound.into(),
+        ));
+        assert_eq!(report.exit_status(ExitStatus::Success), ExitStatus::Success);
+        assert_eq!(
+            report.exit_status(ExitStatus::CandidatesFound),
+            ExitStatus::CandidatesFound
+        );
+
+        report.record(PathError::panic("boom".to_owned()));
+        assert_eq!(report.exit_status(ExitStatus::Success), ExitStatus::Error);
+        assert_eq!(report.exit_status(ExitStatus::Aborted), ExitStatus::Error);
+    }
+}
@@ -0,0 +1,30 @@
+use std::process::ExitCode;
+
+// Process exit codes
+// These are a stable contract for scripts and CI. Document any change in readme.md.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    // Nothing to nuke, or every candidate was nuked
+    Success = 0,
+
+    // Candidates were found but not nuked (--dry-run or --benchmark)
+    CandidatesFound = 1,
+
+    // User declined to nuke
+    Aborted = 2,
+
+    // User confirmed but one or more deletions failed
+    PartialFailure = 3,
+
+    // Fatal error such as invalid arguments, an unreadable starting directory or a panicked scan worker
+    Error = 4,
+
+    // `check` found ignored files exceeding a budget
+    BudgetExceeded = 5,
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> ExitCode {
+        ExitCode::from(status as u8)
+    }
+}
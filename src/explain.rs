@@ -0,0 +1,298 @@
+use crate::cache::ScanCache;
+use crate::exit_code::ExitStatus;
+use crate::ignore_stack::{IgnoreLayer, IgnoreSource, IgnoreStacks};
+use crate::projects::{self, Project};
+use crate::scan::{self, ScanArgs};
+use anyhow::{anyhow, Context};
+use ignore::gitignore::Glob;
+use ignore::Match;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExplainArgs {
+    #[structopt(parse(from_os_str), help = "Path to explain. Must be inside the starting directory")]
+    pub path: PathBuf,
+}
+
+// Final outcome for the explained path
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    // Path, or the ancestor containing it, would be nuked
+    Nuke(PathBuf),
+
+    // Path, or the ancestor containing it, is protected by a whitelist
+    Whitelisted(PathBuf),
+
+    // No pattern matched the path or any ancestor
+    Untouched,
+
+    // Path, or the ancestor containing it, is skipped by `--exclude-dir`
+    Excluded(PathBuf),
+
+    // Path, or the ancestor containing it, is deeper than `--max-depth`
+    TooDeep(PathBuf),
+}
+
+// Walk from the starting directory to `path` and evaluate each ancestor exactly like a scan would
+pub fn run(args: &ExplainArgs, scan_args: &ScanArgs) -> anyhow::Result<ExitStatus> {
+    let starting_dirs = scan::starting_dirs(scan_args)?;
+    let target = fs::canonicalize(&args.path).with_context(|| format!("Failed to find [{}]", args.path.display()))?;
+    let starting_dir = scan::starting_dir_of(&starting_dirs, &target).ok_or_else(|| {
+        anyhow!(
+            "[{:?}] is not inside any starting directory {:?}",
+            target,
+            starting_dirs
+        )
+    })?;
+
+    let stacks = scan::starting_stacks_for(scan_args, starting_dir, false)?;
+
+    println!("🔍 explaining [{:?}] from [{:?}]", target, starting_dir);
+    let verdict = evaluate(starting_dir, stacks, &target, scan_args, true)?;
+    match verdict {
+        Verdict::Nuke(path) if path == target => println!("\n☢️ Verdict: nuke"),
+        Verdict::Nuke(path) => println!("\n☢️ Verdict: nuke, as part of ignored [{:?}]", path),
+        Verdict::Whitelisted(path) if path == target => println!("\n😇 Verdict: whitelisted"),
+        Verdict::Whitelisted(path) => println!("\n😇 Verdict: whitelisted, as part of [{:?}]", path),
+        Verdict::Untouched => println!("\n😐 Verdict: untouched"),
+        Verdict::Excluded(path) if path == target => println!("\n🚧 Verdict: excluded by --exclude-dir"),
+        Verdict::Excluded(path) => println!("\n🚧 Verdict: excluded by --exclude-dir, as part of [{:?}]", path),
+        Verdict::TooDeep(path) if path == target => println!("\n🚧 Verdict: deeper than --max-depth"),
+        Verdict::TooDeep(path) => println!("\n🚧 Verdict: deeper than --max-depth, as part of [{:?}]", path),
+    }
+
+    Ok(ExitStatus::Success)
+}
+
+// Evaluate `target` by walking down from `starting_dir`, entering each ancestor exactly like a scan would
+// `stacks` must be the stacks for `starting_dir`. Prints each step if `verbose` is set.
+// Ancestors whitelisted by a `.gitignore` only protect themselves, like in git. So do ancestors whitelisted
+// by a `.gitnuke` if `--descend-whitelisted` is set. `--exclude-dir`, `--max-depth` and `--projects` apply
+// like in a scan, except that outputs outside their own project, such as a shared cargo target-dir, aren't
+// detected.
+pub fn evaluate(
+    starting_dir: &Path,
+    mut stacks: IgnoreStacks,
+    target: &Path,
+    args: &ScanArgs,
+    verbose: bool,
+) -> anyhow::Result<Verdict> {
+    let relative = target
+        .strip_prefix(starting_dir)
+        .map_err(|_| anyhow!("[{:?}] is not inside starting directory [{:?}]", target, starting_dir))?;
+    let exclude = scan::exclude_matcher(args, starting_dir)?;
+    let uncached = ScanCache::disabled();
+
+    let mut dir = starting_dir.to_owned();
+    for (depth, component) in (1..).zip(relative.components()) {
+        // Add ignores from this directory and print what will be consulted
+        let child_names: Vec<_> = fs::read_dir(&dir)?.flatten().map(|entry| entry.file_name()).collect();
+        stacks = stacks.enter_dir(&dir, &child_names, &uncached, false);
+        if verbose {
+            println!("\n📁 [{:?}]", dir);
+            for layer in stacks.layers() {
+                println!("    consulting [{}]", layer.source);
+            }
+        }
+
+        // The walk stops before evaluating anything deeper
+        let child = dir.join(component);
+        if is_too_deep(args, depth) {
+            if verbose {
+                println!("  [{:?}] is deeper than --max-depth", child);
+            }
+            return Ok(Verdict::TooDeep(child));
+        }
+
+        // Evaluate the next path component
+        let is_dir = fs::metadata(&child)?.is_dir();
+        let is_excluded = || exclude.matched(&child, true).is_ignore();
+        let project = project_output(args, &dir, &child_names, &child);
+        let (action, matched) = classify(args, &stacks, &child, is_dir, is_excluded, project.is_some());
+        if verbose {
+            match (&action, &matched, &project) {
+                (ChildAction::Excluded, _, _) => println!("  [{:?}] is excluded by --exclude-dir", child),
+                (_, Some((layer, m)), _) => println!(
+                    "  [{:?}] matched glob [{}] from [{}]",
+                    child,
+                    m.inner().unwrap().original(),
+                    layer.source
+                ),
+                // Build output of a project rooted in this directory is nuked without any glob
+                (_, None, Some(project)) => println!(
+                    "  [{:?}] is build output of {} project [{:?}]",
+                    child, project.kind, project.root
+                ),
+                (_, None, None) => println!("  [{:?}] matched nothing", child),
+            }
+        }
+        match action {
+            ChildAction::Excluded => return Ok(Verdict::Excluded(child)),
+            ChildAction::Nuke => return Ok(Verdict::Nuke(child)),
+            // The target itself is protected by its whitelist even if its children would be walked
+            ChildAction::Descend if matched.is_some() => {
+                if child == target {
+                    return Ok(Verdict::Whitelisted(child));
+                }
+                if verbose {
+                    println!("  descending into whitelisted [{:?}]", child);
+                }
+            }
+            ChildAction::Keep if matched.is_some() => return Ok(Verdict::Whitelisted(child)),
+            ChildAction::Descend | ChildAction::Keep => {}
+        }
+
+        dir = child;
+    }
+
+    Ok(Verdict::Untouched)
+}
+
+// Project rooted in `dir`, whose children are named `child_names`, that builds into `child`, see `--projects`
+fn project_output(args: &ScanArgs, dir: &Path, child_names: &[OsString], child: &Path) -> Option<Project> {
+    if !args.projects {
+        return None;
+    }
+    projects::detect(dir, child_names, env::var_os("CARGO_TARGET_DIR").as_deref())
+        .into_iter()
+        .find(|output| output.path == child)
+        .map(|output| output.project)
+}
+
+// What the walk does with a single child of a walked directory
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChildAction {
+    // Skipped by `--exclude-dir`, so neither walked nor nuked
+    Excluded,
+
+    // Ignored by a glob, or build output of a project that no glob matched
+    Nuke,
+
+    // Directory that matched nothing, or whose whitelist doesn't protect its subtree
+    Descend,
+
+    // Whitelisted, or a file that matched nothing
+    Keep,
+}
+
+// Glob match that decided a child's action, along with the layer it came from
+pub type ChildMatch<'a> = Option<(&'a IgnoreLayer, Match<&'a Glob>)>;
+
+// Decide what happens to `child`, identically for scans and `explain`
+// `stacks` must be the stacks entered for the child's parent. `is_excluded` is only called for directories.
+// `is_project_output` is whether a project rooted in the parent builds into `child`, see `--projects`.
+pub fn classify<'a>(
+    args: &ScanArgs,
+    stacks: &'a IgnoreStacks,
+    child: &Path,
+    is_dir: bool,
+    is_excluded: impl FnOnce() -> bool,
+    is_project_output: bool,
+) -> (ChildAction, ChildMatch<'a>) {
+    if is_dir && is_excluded() {
+        return (ChildAction::Excluded, None);
+    }
+
+    let matched = stacks.matched(child, is_dir);
+    let action = match &matched {
+        Some((_, m)) if m.is_ignore() => ChildAction::Nuke,
+        Some((layer, _)) if is_dir && descends_into(&layer.source, args.descend_whitelisted) => ChildAction::Descend,
+        Some(_) => ChildAction::Keep,
+        None if is_project_output => ChildAction::Nuke,
+        None if is_dir => ChildAction::Descend,
+        None => ChildAction::Keep,
+    };
+    (action, matched)
+}
+
+// Whether a path `depth` levels below the starting directory is beyond `--max-depth`
+// Such paths are never candidates, though a scan still counts their size.
+pub fn is_too_deep(args: &ScanArgs, depth: usize) -> bool {
+    args.max_depth.is_some_and(|max_depth| depth > max_depth)
+}
+
+// Whether the walk continues into a directory whitelisted by `source`
+fn descends_into(source: &IgnoreSource, descend_whitelisted: bool) -> bool {
+    match source {
+        IgnoreSource::Whitelist => false,
+        source if source.is_protective() => descend_whitelisted,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdicts() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        for dir in [
+            ".git",
+            "target/debug",
+            "keep",
+            "src",
+            "vendor/lib",
+            "web/node_modules/left-pad",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join(".gitnuke"), "!/keep/\n").unwrap();
+        for file in [
+            "target/debug/app",
+            "keep/run.log",
+            "src/main.rs",
+            "vendor/lib/build.log",
+            "web/package.json",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let verdict = |path: &str, args: &[&str]| {
+            let args = ScanArgs::from_iter(std::iter::once("test").chain(args.iter().copied()));
+            let stacks = scan::starting_stacks_for(&args, &root, false).unwrap();
+            evaluate(&root, stacks, &root.join(path), &args, false).unwrap()
+        };
+
+        assert_eq!(verdict("target", &[]), Verdict::Nuke(root.join("target")));
+        assert_eq!(verdict("target/debug/app", &[]), Verdict::Nuke(root.join("target")));
+        assert_eq!(verdict("keep/run.log", &[]), Verdict::Whitelisted(root.join("keep")));
+        assert_eq!(
+            verdict("keep/run.log", &["--descend-whitelisted"]),
+            Verdict::Nuke(root.join("keep/run.log"))
+        );
+        assert_eq!(verdict("src/main.rs", &[]), Verdict::Untouched);
+        assert_eq!(verdict("web/node_modules", &[]), Verdict::Untouched);
+
+        // Scan filters apply
+        assert_eq!(
+            verdict("vendor/lib/build.log", &["--exclude-dir", "vendor"]),
+            Verdict::Excluded(root.join("vendor"))
+        );
+        assert_eq!(
+            verdict("target", &["--max-depth", "1"]),
+            Verdict::Nuke(root.join("target"))
+        );
+        assert_eq!(
+            verdict("target", &["--max-depth", "0"]),
+            Verdict::TooDeep(root.join("target"))
+        );
+        assert_eq!(
+            verdict("vendor/lib/build.log", &["--max-depth", "2"]),
+            Verdict::TooDeep(root.join("vendor/lib/build.log"))
+        );
+        assert_eq!(
+            verdict("vendor/lib/build.log", &["--max-depth", "3"]),
+            Verdict::Nuke(root.join("vendor/lib/build.log"))
+        );
+        assert_eq!(
+            verdict("web/node_modules/left-pad", &["--projects"]),
+            Verdict::Nuke(root.join("web/node_modules"))
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::scan::{self, Candidate};
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+// How candidates are aggregated into buckets, see `--group-by`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    // Closest source control root
+    Repo,
+
+    // Glob that matched, regardless of which ignore file it came from
+    Pattern,
+
+    // First directory below the starting directory containing the path
+    TopDir,
+
+    // File extension
+    Extension,
+}
+
+pub const GROUP_BY_NAMES: &[&str] = &["repo", "pattern", "top-dir", "extension"];
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<GroupBy> {
+        match s {
+            "repo" => Ok(GroupBy::Repo),
+            "pattern" => Ok(GroupBy::Pattern),
+            "top-dir" => Ok(GroupBy::TopDir),
+            "extension" => Ok(GroupBy::Extension),
+            _ => Err(anyhow!("Unknown grouping [{}]", s)),
+        }
+    }
+}
+
+// Candidates sharing a key
+#[derive(Debug)]
+pub struct Bucket {
+    pub key: String,
+    pub size: u64,
+    pub count: usize,
+    pub num_repos: usize,
+}
+
+impl GroupBy {
+    // Key of the bucket containing `candidate`
+    pub fn key(self, candidate: &Candidate, starting_dirs: &[PathBuf]) -> String {
+        match self {
+            GroupBy::Repo => match &candidate.repo_root {
+                Some(repo_root) => repo_root.display().to_string(),
+                None => "(no repo)".to_owned(),
+            },
+            GroupBy::Pattern => match (&candidate.glob, &candidate.project) {
+                (Some(glob), _) => glob.clone(),
+                (None, Some(project)) => format!("({} output)", project.kind),
+                (None, None) => "(none)".to_owned(),
+            },
+            GroupBy::TopDir => match scan::starting_dir_of(starting_dirs, &candidate.path) {
+                Some(starting_dir) => {
+                    let relative = candidate.path.strip_prefix(starting_dir).unwrap_or(&candidate.path);
+                    match relative.components().next() {
+                        Some(top) => starting_dir.join(top).display().to_string(),
+                        None => starting_dir.display().to_string(),
+                    }
+                }
+                None => candidate.path.display().to_string(),
+            },
+            GroupBy::Extension => match candidate.path.extension() {
+                Some(extension) => format!(".{}", extension.to_string_lossy()),
+                None => "(none)".to_owned(),
+            },
+        }
+    }
+
+    // Aggregate candidates into buckets, largest first
+    pub fn group(self, candidates: &[Candidate], starting_dirs: &[PathBuf]) -> Vec<Bucket> {
+        let mut buckets: HashMap<String, (Bucket, HashSet<Option<&PathBuf>>)> = Default::default();
+        for candidate in candidates {
+            let key = self.key(candidate, starting_dirs);
+            let (bucket, repos) = buckets.entry(key.clone()).or_insert_with(|| {
+                let bucket = Bucket {
+                    key,
+                    size: 0,
+                    count: 0,
+                    num_repos: 0,
+                };
+                (bucket, Default::default())
+            });
+            bucket.size += candidate.size;
+            bucket.count += 1;
+            repos.insert(candidate.repo_root.as_ref());
+            bucket.num_repos = repos.len();
+        }
+
+        let mut buckets: Vec<_> = buckets.into_iter().map(|(_, (bucket, _))| bucket).collect();
+        buckets.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.key.cmp(&b.key)));
+        buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(path: &str, size: u64, repo: &str, glob: &str) -> Candidate {
+        Candidate {
+            size,
+            repo_root: Some(PathBuf::from(repo)),
+            glob: Some(glob.to_owned()),
+            ..Candidate::new(PathBuf::from(path))
+        }
+    }
+
+    fn candidates() -> Vec<Candidate> {
+        vec![
+            candidate("/src/a/node_modules", 100, "/src/a", "node_modules/"),
+            candidate("/src/b/node_modules", 200, "/src/b", "node_modules/"),
+            candidate("/src/b/target", 400, "/src/b", "target/"),
+            candidate("/src/b/out.log", 1, "/src/b", "*.log"),
+        ]
+    }
+
+    #[test]
+    fn group_by_pattern() {
+        let buckets = GroupBy::Pattern.group(&candidates(), &[PathBuf::from("/src")]);
+        let summary: Vec<_> = buckets
+            .iter()
+            .map(|b| (b.key.as_str(), b.size, b.count, b.num_repos))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("target/", 400, 1, 1), ("node_modules/", 300, 2, 2), ("*.log", 1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn group_by_repo() {
+        let buckets = GroupBy::Repo.group(&candidates(), &[PathBuf::from("/src")]);
+        let summary: Vec<_> = buckets.iter().map(|b| (b.key.as_str(), b.size, b.count)).collect();
+        assert_eq!(summary, vec![("/src/b", 601, 3), ("/src/a", 100, 1)]);
+    }
+
+    #[test]
+    fn group_by_top_dir_and_extension() {
+        let keys: Vec<_> = candidates()
+            .iter()
+            .map(|c| GroupBy::TopDir.key(c, &[PathBuf::from("/src")]))
+            .collect();
+        assert_eq!(keys, vec!["/src/a", "/src/b", "/src/b", "/src/b"]);
+
+        let keys: Vec<_> = candidates()
+            .iter()
+            .map(|c| GroupBy::Extension.key(c, &[PathBuf::from("/src")]))
+            .collect();
+        assert_eq!(keys, vec!["(none)", "(none)", "(none)", ".log"]);
+    }
+}
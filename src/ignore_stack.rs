@@ -0,0 +1,312 @@
+use crate::cache::ScanCache;
+use crate::presets::Preset;
+use cactus::ArcCactus;
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use ignore::Match;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Where a set of ignore patterns came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IgnoreSource {
+    // Built-in whitelist protecting source control and ignore files
+    Whitelist,
+
+    // User's global gitignore
+    Global,
+
+    // Built-in patterns for a type of project, see `--preset`
+    Preset(&'static str),
+
+    // A `.gitignore` or `.gitnuke` file
+    File(PathBuf),
+}
+
+impl IgnoreSource {
+    // Whitelists from protective sources keep their whole subtree. Whitelists from any other source
+    // only mean "not ignored", exactly like git, so their children are still evaluated.
+    pub fn is_protective(&self) -> bool {
+        match self {
+            IgnoreSource::Whitelist => true,
+            IgnoreSource::File(path) => path.file_name().is_some_and(|name| name == ".gitnuke"),
+            IgnoreSource::Global | IgnoreSource::Preset(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for IgnoreSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreSource::Whitelist => write!(f, "built-in whitelist"),
+            IgnoreSource::Global => write!(f, "global gitignore"),
+            IgnoreSource::Preset(name) => write!(f, "{} preset", name),
+            IgnoreSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+// Patterns from a single source
+// Clone is required by ArcCactus but layers themselves are never cloned
+#[derive(Clone)]
+pub struct IgnoreLayer {
+    pub source: IgnoreSource,
+    pub ignore: Gitignore,
+
+    // Loaded from above the starting directory by `--root`
+    // Ancestors of the starting directory were never walked, so they are matched along with the path.
+    pub inherited: bool,
+}
+
+// The .gitignore and .gitnuke stacks that apply to a directory
+// Stacks are cactus stacks so sibling directories share their parent's layers.
+#[derive(Clone)]
+pub struct IgnoreStacks {
+    pub gitignore: ArcCactus<IgnoreLayer>,
+    pub gitnuke: ArcCactus<IgnoreLayer>,
+
+    // Lowest precedence tier, added wherever a preset's marker file is found
+    // Reset at source control roots, like `gitignore`.
+    pub presets: ArcCactus<IgnoreLayer>,
+
+    // Closest source control root containing the directory, if any
+    pub repo_root: Option<Arc<Path>>,
+
+    // `gitignore` is reset to this at source control roots
+    global: ArcCactus<IgnoreLayer>,
+
+    enabled_presets: Arc<[&'static Preset]>,
+}
+
+impl IgnoreStacks {
+    // Build stacks for the starting directory
+    // If `root` is set then ignores from parent directories are included up to `root` or a source control root
+    pub fn new(
+        starting_dir: &Path,
+        include_global_ignore: bool,
+        root: Option<&Path>,
+        enabled_presets: Vec<&'static Preset>,
+        verbose: bool,
+    ) -> anyhow::Result<IgnoreStacks> {
+        // Start .gitignore and .gitnuke stacks with empty root
+        let mut gitignore_tip = ArcCactus::new();
+        let mut gitnuke_tip = ArcCactus::new();
+
+        // Add whitelist to gitnuke stack
+        let ignore_whitelist = GitignoreBuilder::new(starting_dir)
+            .add_line(None, "!.git")?
+            .add_line(None, "!.hg")?
+            .add_line(None, "!.gitignore")?
+            .add_line(None, "!.gitnuke")?
+            // TODO: cmdline whitelist?
+            .build()?;
+        gitnuke_tip = gitnuke_tip.child(IgnoreLayer {
+            source: IgnoreSource::Whitelist,
+            ignore: ignore_whitelist,
+            inherited: false,
+        });
+
+        // Add global ignore (if requested)
+        let mut global_ignore = gitignore_tip.clone();
+        if include_global_ignore {
+            let (global_gitignore, err) = GitignoreBuilder::new(starting_dir).build_global();
+            if err.is_none() && global_gitignore.num_ignores() > 0 {
+                gitignore_tip = gitignore_tip.child(IgnoreLayer {
+                    source: IgnoreSource::Global,
+                    ignore: global_gitignore,
+                    inherited: false,
+                });
+                global_ignore = gitignore_tip.clone();
+            }
+        }
+
+        // Search for ignores in parent directories
+        // Stop if .git or .hg is present
+        if let Some(root) = root {
+            let uncached = ScanCache::disabled();
+            let mut parent_gitignore: Vec<_> = Default::default();
+            let mut parent_gitnuke: Vec<_> = Default::default();
+            let mut dir: &Path = starting_dir;
+            while let Some(parent_path) = dir.parent() {
+                // Push `.gitignore` patterns
+                if let Some(ignore) = build_ignore(parent_path, ".gitignore", &uncached, verbose) {
+                    parent_gitignore.push(IgnoreLayer {
+                        inherited: true,
+                        ..ignore
+                    });
+                }
+
+                // Push `.gitnuke` patterns (higher priority than `.gitignore`)
+                if let Some(ignore) = build_ignore(parent_path, ".gitnuke", &uncached, verbose) {
+                    parent_gitnuke.push(IgnoreLayer {
+                        inherited: true,
+                        ..ignore
+                    });
+                }
+
+                // Stop at source control roots
+                // Their `.git/info/exclude` has lower priority than any `.gitignore`
+                if is_vcs_root(parent_path) {
+                    if let Some(exclude) = build_git_exclude(parent_path, &uncached, verbose) {
+                        parent_gitignore.push(IgnoreLayer {
+                            inherited: true,
+                            ..exclude
+                        });
+                    }
+                    break;
+                }
+
+                // Stop at specified root
+                if root == parent_path {
+                    break;
+                }
+
+                dir = parent_path;
+            }
+
+            // Push parent gitignores onto gitignore_stack
+            for ignore in parent_gitignore.into_iter().rev() {
+                gitignore_tip = gitignore_tip.child(ignore);
+            }
+
+            // Push parent gitnukes onto gitnuke_stack
+            for ignore in parent_gitnuke.into_iter().rev() {
+                gitnuke_tip = gitnuke_tip.child(ignore);
+            }
+        }
+
+        Ok(IgnoreStacks {
+            gitignore: gitignore_tip,
+            gitnuke: gitnuke_tip,
+            presets: ArcCactus::new(),
+            repo_root: starting_dir
+                .ancestors()
+                .skip(1)
+                .find(|dir| is_vcs_root(dir))
+                .map(Arc::from),
+            global: global_ignore,
+            enabled_presets: enabled_presets.into(),
+        })
+    }
+
+    // Stacks that apply to the children of `dir`, which are named `child_names`
+    // Ignore files unchanged since the last run are read from `cache`.
+    pub fn enter_dir(&self, dir: &Path, child_names: &[OsString], cache: &ScanCache, verbose: bool) -> IgnoreStacks {
+        let mut stacks = self.clone();
+
+        // Check for source control root
+        if is_vcs_root(dir) {
+            // Reset ignore tip, and presets from the enclosing repo
+            stacks.gitignore = self.global.clone();
+            stacks.presets = ArcCactus::new();
+            stacks.repo_root = Some(Arc::from(dir));
+
+            // Add `.git/info/exclude` patterns, below every `.gitignore` in the repo
+            if let Some(exclude) = build_git_exclude(dir, cache, verbose) {
+                stacks.gitignore = stacks.gitignore.child(exclude);
+            }
+        }
+
+        // Add `.gitignore` patterns
+        if let Some(ignore) = build_ignore(dir, ".gitignore", cache, verbose) {
+            stacks.gitignore = stacks.gitignore.child(ignore);
+        }
+
+        // Add `.gitnuke` patterns
+        if let Some(ignore) = build_ignore(dir, ".gitnuke", cache, verbose) {
+            stacks.gitnuke = stacks.gitnuke.child(ignore);
+        }
+
+        // Add patterns for presets whose marker is present
+        for preset in self.enabled_presets.iter() {
+            if preset.is_marked(dir, child_names) {
+                if let Ok(ignore) = preset.build(dir) {
+                    if verbose {
+                        println!("Loaded: [{} preset] for [{}]", preset.name, dir.display());
+                    }
+                    stacks.presets = stacks.presets.child(IgnoreLayer {
+                        source: IgnoreSource::Preset(preset.name),
+                        ignore,
+                        inherited: false,
+                    });
+                }
+            }
+        }
+
+        stacks
+    }
+
+    // Every layer in the order it is consulted
+    // All `.gitnuke` layers have higher precedence than any `.gitignore` layer, which have higher
+    // precedence than any preset.
+    pub fn layers(&self) -> impl Iterator<Item = &IgnoreLayer> {
+        self.gitnuke
+            .vals()
+            .chain(self.gitignore.vals())
+            .chain(self.presets.vals())
+    }
+
+    // Test if path is ignored, whitelisted, or neither
+    // Returns first match that is either ignored or whitelisted, along with the layer it came from
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<(&IgnoreLayer, Match<&Glob>)> {
+        self.layers()
+            .map(|layer| {
+                let m = if layer.inherited {
+                    layer.ignore.matched_path_or_any_parents(path, is_dir)
+                } else {
+                    layer.ignore.matched(path, is_dir)
+                };
+                (layer, m)
+            })
+            .find(|(_, m)| !m.is_none())
+    }
+}
+
+pub fn is_vcs_root(dir: &Path) -> bool {
+    dir.join(".git").exists() || dir.join(".hg").exists()
+}
+
+// Helper to add .gitignore and .gitnuke files
+fn build_ignore(dir: &Path, filename: &str, cache: &ScanCache, verbose: bool) -> Option<IgnoreLayer> {
+    build_ignore_rooted(dir, dir.join(filename), cache, verbose)
+}
+
+// Helper to add a repo's `.git/info/exclude`, whose patterns are relative to the repo root
+fn build_git_exclude(repo_root: &Path, cache: &ScanCache, verbose: bool) -> Option<IgnoreLayer> {
+    build_ignore_rooted(
+        repo_root,
+        repo_root.join(".git").join("info").join("exclude"),
+        cache,
+        verbose,
+    )
+}
+
+fn build_ignore_rooted(root: &Path, ignore_path: PathBuf, cache: &ScanCache, verbose: bool) -> Option<IgnoreLayer> {
+    if let Some(meta) = fs::metadata(&ignore_path).ok().filter(Metadata::is_file) {
+        let mut ignore_builder = GitignoreBuilder::new(root);
+        match cache.ignore_lines(&ignore_path, &meta) {
+            Some(lines) => {
+                for line in &lines {
+                    let _ = ignore_builder.add_line(Some(ignore_path.clone()), line);
+                }
+            }
+            // Not valid UTF-8, so left to the parser without caching
+            None => {
+                ignore_builder.add(&ignore_path);
+            }
+        }
+        if let Ok(ignore) = ignore_builder.build() {
+            if verbose {
+                println!("Loaded: [{}]", ignore_path.display());
+            }
+            return Some(IgnoreLayer {
+                source: IgnoreSource::File(ignore_path),
+                ignore,
+                inherited: false,
+            });
+        }
+    }
+    None
+}
@@ -1,16 +1,59 @@
 use crossbeam_deque::{Injector, Stealer, Worker};
+use std::any::Any;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-pub fn run_recursive_job<IN, OUT, JOB>(initial: Vec<IN>, job: JOB, num_workers: usize) -> Vec<OUT>
+// Error produced while running a single job item
+#[derive(Debug)]
+pub enum JobError<ERR> {
+    // Job returned an error for its item
+    Failed(ERR),
+
+    // Job panicked while processing an item. Any work it would have pushed is lost.
+    Panicked(String),
+}
+
+impl<ERR: fmt::Display> fmt::Display for JobError<ERR> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Failed(e) => write!(f, "{}", e),
+            JobError::Panicked(msg) => write!(f, "job panicked: {}", msg),
+        }
+    }
+}
+
+// Combined output of every job item
+pub struct JobResults<OUT, ERR> {
+    pub outputs: Vec<OUT>,
+    pub errors: Vec<JobError<ERR>>,
+}
+
+impl<OUT, ERR> JobResults<OUT, ERR> {
+    fn new() -> JobResults<OUT, ERR> {
+        JobResults {
+            outputs: Default::default(),
+            errors: Default::default(),
+        }
+    }
+
+    fn append(&mut self, mut other: JobResults<OUT, ERR>) {
+        self.outputs.append(&mut other.outputs);
+        self.errors.append(&mut other.errors);
+    }
+}
+
+pub fn run_recursive_job<IN, OUT, ERR, JOB>(initial: Vec<IN>, job: JOB, num_workers: usize) -> JobResults<OUT, ERR>
 where
     IN: Send,
     OUT: Send,
-    JOB: Fn(IN, &Worker<IN>) -> Option<OUT> + Clone + Send,
+    ERR: Send,
+    JOB: Fn(IN, &Worker<IN>) -> Result<OUT, ERR> + Clone + Send,
 {
     // Create crossbeam_deque injector/worker/stealers
     let injector = Injector::new();
-    let workers: Vec<_> = (0..num_workers).map(|_| Worker::new_lifo()).collect();
+    let workers: Vec<_> = (0..num_workers.max(1)).map(|_| Worker::new_lifo()).collect();
     let stealers: Vec<_> = workers.iter().map(|w| w.stealer()).collect();
     let active_counter = ActiveCounter::new();
 
@@ -20,7 +63,7 @@ where
     }
 
     // Create single scope to contain all workers
-    let result: Vec<OUT> = crossbeam_utils::thread::scope(|scope| {
+    let scope_result = crossbeam_utils::thread::scope(|scope| {
         // Container for all workers
         let mut worker_scopes: Vec<_> = Default::default();
 
@@ -35,7 +78,7 @@ where
             // Create scope for single worker
             let s = scope.spawn(move |_| {
                 // results of this worker
-                let mut worker_results: Vec<_> = Default::default();
+                let mut worker_results = JobResults::new();
 
                 // backoff spinner for sleeping
                 let backoff = crossbeam_utils::Backoff::new();
@@ -44,13 +87,17 @@ where
                 loop {
                     {
                         // look for work
-                        let _ = counter_copy.take_token();
+                        // token must stay alive while working so idle workers don't exit early
+                        let _token = counter_copy.take_token();
                         while let Some(item) = find_task(&worker, injector_borrow, &stealers_copy) {
                             backoff.reset();
 
                             // do work
-                            if let Some(result) = job_copy(item, &worker) {
-                                worker_results.push(result);
+                            // panics are caught per item so one bad item doesn't take down the worker
+                            match panic::catch_unwind(AssertUnwindSafe(|| job_copy(item, &worker))) {
+                                Ok(Ok(result)) => worker_results.outputs.push(result),
+                                Ok(Err(e)) => worker_results.errors.push(JobError::Failed(e)),
+                                Err(payload) => worker_results.errors.push(JobError::Panicked(panic_message(payload))),
                             }
                         }
                     }
@@ -71,15 +118,37 @@ where
         }
 
         // run all workers to completion and combine their results
-        worker_scopes
-            .into_iter()
-            .filter_map(|s| s.join().ok())
-            .flatten()
-            .collect()
-    })
-    .unwrap();
+        // a worker that fails to join is reported as an error rather than silently dropped
+        let mut results = JobResults::new();
+        for s in worker_scopes {
+            match s.join() {
+                Ok(worker_results) => results.append(worker_results),
+                Err(payload) => results.errors.push(JobError::Panicked(panic_message(payload))),
+            }
+        }
+        results
+    });
+
+    // All workers were joined above so the scope itself can only fail if that invariant breaks
+    match scope_result {
+        Ok(results) => results,
+        Err(payload) => {
+            let mut results = JobResults::new();
+            results.errors.push(JobError::Panicked(panic_message(payload)));
+            results
+        }
+    }
+}
 
-    result
+// Extract a printable message from a panic payload
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_owned()
+    }
 }
 
 fn find_task<T>(local: &Worker<T>, global: &Injector<T>, stealers: &[Stealer<T>]) -> Option<T> {
@@ -139,13 +208,28 @@ impl Drop for ActiveToken {
 mod tests {
     use super::*;
 
-    fn job(value: i64, worker: &Worker<i64>) -> Option<i64> {
+    fn job(value: i64, worker: &Worker<i64>) -> Result<i64, String> {
+        if value > 0 {
+            worker.push(value - 1);
+        }
+        Ok(value)
+    }
+
+    fn failing_job(value: i64, worker: &Worker<i64>) -> Result<i64, String> {
+        if value % 10 == 0 {
+            return Err(format!("bad value {}", value));
+        }
         if value > 0 {
             worker.push(value - 1);
-            Some(value)
-        } else {
-            None
         }
+        Ok(value)
+    }
+
+    fn panicking_job(value: i64, worker: &Worker<i64>) -> Result<i64, String> {
+        if value == 5 {
+            panic!("value was {}", value);
+        }
+        job(value, worker)
     }
 
     fn instant_sum(value: i64) -> i64 {
@@ -159,13 +243,15 @@ mod tests {
     fn recursive_sum(value: i64, num_threads: usize) -> i64 {
         let data = vec![value];
         let results = run_recursive_job(data, job, num_threads);
-        results.iter().sum()
+        assert!(results.errors.is_empty());
+        results.outputs.iter().sum()
     }
 
     fn recursive_sums(values: &[i64], num_threads: usize) -> i64 {
-        let data: Vec<_> = values.iter().cloned().collect();
+        let data: Vec<_> = values.to_vec();
         let results = run_recursive_job(data, job, num_threads);
-        results.iter().sum()
+        assert!(results.errors.is_empty());
+        results.outputs.iter().sum()
     }
 
     #[test]
@@ -207,4 +293,21 @@ mod tests {
         let data: Vec<_> = (0..10000).collect();
         assert_eq!(instant_sums(&data), recursive_sums(&data, 6));
     }
+
+    #[test]
+    fn errors_are_returned() {
+        let mut results = run_recursive_job(vec![25], failing_job, 4);
+        results.outputs.sort();
+        assert_eq!(results.outputs, vec![21, 22, 23, 24, 25]);
+        assert_eq!(results.errors.len(), 1);
+        assert!(matches!(&results.errors[0], JobError::Failed(e) if e == "bad value 20"));
+    }
+
+    #[test]
+    fn panics_are_returned() {
+        let results = run_recursive_job(vec![10], panicking_job, 4);
+        assert_eq!(results.outputs.iter().sum::<i64>(), 10 + 9 + 8 + 7 + 6);
+        assert_eq!(results.errors.len(), 1);
+        assert!(matches!(&results.errors[0], JobError::Panicked(msg) if msg == "value was 5"));
+    }
 }
@@ -0,0 +1,204 @@
+use crate::scan::Candidate;
+use crate::units::ByteSize;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::fs;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+// Options controlling the order and length of candidate listings
+// The listing shown is exactly what gets nuked, so these are applied before the nuke prompt.
+#[derive(StructOpt, Debug, Default)]
+pub struct ListArgs {
+    #[structopt(
+        long,
+        global = true,
+        default_value = "size",
+        possible_values = SORT_NAMES,
+        help = "Orders candidates by size, path, mtime or count of files. Largest, oldest and most files come first"
+    )]
+    pub sort: SortKey,
+
+    #[structopt(long, global = true, help = "Reverses the order chosen by --sort")]
+    pub reverse: bool,
+
+    #[structopt(long, global = true, help = "Keeps only the first N candidates after sorting")]
+    pub top: Option<usize>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Collapses candidates smaller than this size, such as 10MB, into one line. They are still nuked"
+    )]
+    pub collapse_below: Option<ByteSize>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    // Largest first
+    #[default]
+    Size,
+
+    // Alphabetical
+    Path,
+
+    // Least recently modified first
+    Mtime,
+
+    // Most files first
+    Count,
+}
+
+pub const SORT_NAMES: &[&str] = &["size", "path", "mtime", "count"];
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<SortKey> {
+        match s {
+            "size" => Ok(SortKey::Size),
+            "path" => Ok(SortKey::Path),
+            "mtime" => Ok(SortKey::Mtime),
+            "count" => Ok(SortKey::Count),
+            _ => Err(anyhow!("Unknown sort key [{}]", s)),
+        }
+    }
+}
+
+// Candidates folded into a single line by `--collapse-below`
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Others {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+// Sort candidates, see `--sort` and `--reverse`
+// Ties are broken by path so the order is always the same.
+pub fn arrange(mut candidates: Vec<Candidate>, args: &ListArgs) -> Vec<Candidate> {
+    candidates.sort_by(|a, b| a.path.cmp(&b.path));
+    match args.sort {
+        SortKey::Size => candidates.sort_by_key(|c| Reverse(c.size)),
+        SortKey::Path => (),
+        SortKey::Mtime => candidates.sort_by_cached_key(|c| {
+            // Paths whose mtime can't be read go last
+            let mtime = fs::metadata(&c.path).and_then(|meta| meta.modified()).ok();
+            (mtime.is_none(), mtime)
+        }),
+        SortKey::Count => candidates.sort_by_key(|c| Reverse(c.num_files)),
+    }
+
+    if args.reverse {
+        candidates.reverse();
+    }
+    candidates
+}
+
+// Keep the first `--top` arranged candidates
+// Applied after anything that holds candidates back, so those don't take up slots.
+pub fn top(mut candidates: Vec<Candidate>, args: &ListArgs) -> Vec<Candidate> {
+    if let Some(top) = args.top {
+        candidates.truncate(top);
+    }
+    candidates
+}
+
+// Keep candidates, in order, until their total size reaches `target`
+pub fn reclaim(mut candidates: Vec<Candidate>, target: u64) -> Vec<Candidate> {
+    let mut total = 0;
+    let count = candidates
+        .iter()
+        .take_while(|c| {
+            let needed = total < target;
+            total += c.size;
+            needed
+        })
+        .count();
+    candidates.truncate(count);
+    candidates
+}
+
+// Split candidates into those listed individually and those collapsed into one line
+pub fn collapse<'a>(candidates: &'a [Candidate], args: &ListArgs) -> (Vec<&'a Candidate>, Option<Others>) {
+    let below = match args.collapse_below {
+        Some(below) => below.0,
+        None => return (candidates.iter().collect(), None),
+    };
+
+    let mut others = Others::default();
+    let mut shown = Vec::new();
+    for candidate in candidates {
+        if candidate.size < below {
+            others.count += 1;
+            others.bytes += candidate.size;
+        } else {
+            shown.push(candidate);
+        }
+    }
+
+    (shown, Some(others).filter(|others| others.count > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn candidates() -> Vec<Candidate> {
+        vec![("b", 10, 3), ("a", 300, 1), ("c", 20, 7), ("d", 10, 2)]
+            .into_iter()
+            .map(|(path, size, num_files)| Candidate {
+                size,
+                num_files,
+                ..Candidate::new(PathBuf::from(path))
+            })
+            .collect()
+    }
+
+    fn paths(candidates: &[Candidate]) -> Vec<&str> {
+        candidates.iter().map(|c| c.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn sort_keys() {
+        let arranged = |sort, reverse| {
+            paths(&arrange(
+                candidates(),
+                &ListArgs {
+                    sort,
+                    reverse,
+                    ..Default::default()
+                },
+            ))
+            .join("")
+        };
+        assert_eq!(arranged(SortKey::Size, false), "acbd");
+        assert_eq!(arranged(SortKey::Size, true), "dbca");
+        assert_eq!(arranged(SortKey::Path, false), "abcd");
+        assert_eq!(arranged(SortKey::Count, false), "cbda");
+    }
+
+    #[test]
+    fn top_and_collapse() {
+        let args = ListArgs {
+            top: Some(3),
+            collapse_below: Some(ByteSize(15)),
+            ..Default::default()
+        };
+        let arranged = top(arrange(candidates(), &args), &args);
+        assert_eq!(paths(&arranged), vec!["a", "c", "b"]);
+
+        let (shown, others) = collapse(&arranged, &args);
+        assert_eq!(shown.len(), 2);
+        assert_eq!(others, Some(Others { count: 1, bytes: 10 }));
+    }
+
+    #[test]
+    fn reclaim_until_target() {
+        let arranged = || arrange(candidates(), &ListArgs::default());
+        assert_eq!(paths(&reclaim(arranged(), 300)), vec!["a"]);
+        assert_eq!(paths(&reclaim(arranged(), 301)), vec!["a", "c"]);
+        assert_eq!(paths(&reclaim(arranged(), 0)), Vec::<&str>::new());
+        assert_eq!(reclaim(arranged(), 1_000_000).len(), 4);
+    }
+}
@@ -1,15 +1,47 @@
-use anyhow::{anyhow, Context};
-use cactus::ArcCactus;
-use crossbeam_deque::Worker;
-use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use audit::{AuditLog, HistoryArgs, Outcome};
+use busy::BusyCheck;
+use cache::{CacheCommand, ScanCache};
+use check::CheckArgs;
+use errors::{ErrorKind, ErrorReport};
+use exit_code::ExitStatus;
+use explain::ExplainArgs;
+use group::GroupBy;
 use itertools::Itertools;
-use num_format::{Locale, ToFormattedString};
-use std::path::{Path, PathBuf};
-use std::time::Instant;
-use std::{env, fs};
+use listing::ListArgs;
+use processes::OpenPaths;
+use projects::Project;
+use report::{DiffArgs, SavedReport};
+use revalidate::Revalidator;
+use safety::{Rails, SafetyArgs};
+use scan::ScanArgs;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 use structopt::StructOpt;
-
+use units::{ByteSize, Units};
+
+mod audit;
+mod busy;
+mod cache;
+mod check;
+mod delete;
+mod errors;
+mod exit_code;
+mod explain;
+mod group;
+mod ignore_stack;
 mod job_system;
+mod listing;
+mod presets;
+mod processes;
+mod projects;
+mod report;
+mod revalidate;
+mod safety;
+mod scan;
+mod units;
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -19,341 +51,328 @@ mod job_system;
 If a .gitnuke is found its patterns will be used with higher precedence than any .gitignore from the same directory."
 )]
 struct Opts {
-    #[structopt(short, long, parse(from_os_str), help = "Root directory to start search")]
-    directory: Option<PathBuf>,
-
-    #[structopt(
-        short,
-        long,
-        parse(from_os_str),
-        help = "Include .gitignores between root and target directory"
-    )]
-    root: Option<PathBuf>,
+    #[structopt(flatten)]
+    scan: ScanArgs,
 
-    #[structopt(long, default_value = "0", help = "Minimum size, in bytes, to nuke")]
-    min_file_size: u64,
+    #[structopt(flatten)]
+    list: ListArgs,
 
-    #[structopt(long, help = "Number of threads to use. Default: num physical cores")]
-    num_threads: Option<usize>,
+    #[structopt(flatten)]
+    safety: SafetyArgs,
 
     #[structopt(short, long, help = "Auto-quit after walking directory")]
     benchmark: bool,
 
-    #[structopt(long, help = "Prints which glob and which .gitignore matched each path")]
-    print_glob_matches: bool,
-
-    #[structopt(long, help = "Include global .gitignore for matches")]
-    include_global_ignore: bool,
-
-    #[structopt(long, help = "Prints errors if encountered")]
+    #[structopt(
+        long,
+        global = true,
+        help = "Prints every error encountered. Error counts are always summarised"
+    )]
     print_errors: bool,
 
+    #[structopt(
+        long,
+        global = true,
+        default_value = "si",
+        possible_values = units::UNITS_NAMES,
+        help = "Prints sizes in powers of 1000 (KB, MB), powers of 1024 (KiB, MiB) or exact bytes"
+    )]
+    units: Units,
+
     #[structopt(long, help = "Preview which files will be nuked")]
     dry_run: bool,
-}
 
-fn main() -> anyhow::Result<()> {
-    let start = Instant::now();
+    #[structopt(
+        long,
+        possible_values = group::GROUP_BY_NAMES,
+        help = "Summarises candidates into buckets by repo, pattern, top-dir or extension instead of listing each one"
+    )]
+    group_by: Option<GroupBy>,
 
-    // Parse args
-    let opt = Opts::from_args();
-    let num_threads: usize = opt.num_threads.unwrap_or_else(|| num_cpus::get_physical());
-    let root: Option<PathBuf> = opt
-        .root
-        .clone()
-        .map(|s| s.into())
-        .map(|s: PathBuf| std::fs::canonicalize(s).unwrap());
-
-    // Helpers to print Result error if print_errors is true
-    let check_error = |e: anyhow::Result<()>| {
-        if opt.print_errors {
-            if let Err(e) = e {
-                println!("Error: [{:#}]", e);
-            }
-        }
-    };
+    #[structopt(
+        long,
+        requires = "group-by",
+        number_of_values = 1,
+        help = "Restricts listing and nuking to the bucket with this key, as printed by --group-by. May be repeated"
+    )]
+    bucket: Vec<String>,
 
-    // Helper to generate a context string if print_errors is true
-    macro_rules! path_context {
-        ($func:expr, $path:expr) => {{
-            if opt.print_errors {
-                format!("{} {}", $func, $path.display())
-            } else {
-                "".to_owned()
-            }
-        }};
-    }
+    #[structopt(
+        long,
+        help = "Nukes candidates in listed order until this much space, such as 50GB, is reclaimed. Largest first unless --sort says otherwise"
+    )]
+    reclaim: Option<ByteSize>,
 
-    // Helper to add .gitignore and .gitnuke files
-    let build_ignore = |dir: &Path, filename: &str| -> Option<Gitignore> {
-        let ignore_path = dir.join(filename);
-        if ignore_path.exists() {
-            let mut ignore_builder = GitignoreBuilder::new(dir);
-            ignore_builder.add(ignore_path.clone());
-            if let Ok(ignore) = ignore_builder.build() {
-                if opt.print_glob_matches {
-                    println!("Loaded: [{}]", ignore_path.display());
-                }
-                return Some(ignore);
-            }
-        }
-        None
-    };
+    #[structopt(
+        long,
+        help = "Treats candidates containing anything modified within this many minutes as busy. Busy candidates are not nuked"
+    )]
+    busy_within: Option<u64>,
 
-    // Determine starting dir
-    let starting_dir: std::path::PathBuf = match &opt.directory {
-        Some(path) => env::current_dir()?.join(path),
-        None => env::current_dir()?,
-    };
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Saves every ignored path and its size to this file, for comparing runs with diff"
+    )]
+    save_report: Option<PathBuf>,
 
-    // Verify starting dir is valid
-    if !starting_dir.exists() {
-        return Err(anyhow!("Directory [{:?}] does not exist", starting_dir));
-    } else if !starting_dir.is_dir() {
-        return Err(anyhow!("[{:?}] is not a directory", starting_dir));
-    }
-    let starting_dir = std::fs::canonicalize(starting_dir)?;
-
-    // Start .gitignore and .gitnuke stacks with empty root
-    let mut gitignore_tip = ArcCactus::new();
-    let mut gitnuke_tip = ArcCactus::new();
-
-    // Add whitelist to gitnuke stack
-    let ignore_whitelist = GitignoreBuilder::new(starting_dir.clone())
-        .add_line(None, "!.git")?
-        .add_line(None, "!.hg")?
-        .add_line(None, "!.gitignore")?
-        .add_line(None, "!.gitnuke")?
-        // TODO: cmdline whitelist?
-        .build()?;
-    gitnuke_tip = gitnuke_tip.child(ignore_whitelist);
-
-    // Add global ignore (if requested)
-    let mut global_ignore = gitignore_tip.clone();
-    if opt.include_global_ignore {
-        let (global_gitignore, err) = GitignoreBuilder::new(starting_dir.clone()).build_global();
-        if err.is_none() && global_gitignore.num_ignores() > 0 {
-            gitignore_tip = gitignore_tip.child(global_gitignore);
-            global_ignore = gitignore_tip.clone();
-        }
-    }
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
 
-    // Search for ignores in parent directories
-    // Stop if .git or .hg is present
-    if root.is_some() {
-        let mut parent_gitignore: Vec<_> = Default::default();
-        let mut parent_gitnuke: Vec<_> = Default::default();
-        let mut dir: &std::path::Path = &starting_dir;
-        while let Some(parent_path) = dir.parent() {
-            // Push `.gitignore` patterns
-            if let Some(ignore) = build_ignore(&parent_path, ".gitignore") {
-                parent_gitignore.push(ignore);
-            }
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(about = "Manages the cache of directory listings reused between runs")]
+    Cache(CacheCommand),
 
-            // Push `.gitnuke` patterns (higher priority than `.gitignore`)
-            if let Some(ignore) = build_ignore(&parent_path, ".gitnuke") {
-                parent_gitnuke.push(ignore);
-            }
+    #[structopt(about = "Fails if ignored files exceed a size budget. Nothing is deleted")]
+    Check(CheckArgs),
 
-            // Stop at source control roots
-            if parent_path.join(".git").exists() || parent_path.join(".hg").exists() {
-                break;
-            }
+    #[structopt(about = "Shows which ignored paths appeared, disappeared, grew or shrank between two saved reports")]
+    Diff(DiffArgs),
 
-            // Stop at specified root
-            if let Some(root) = &root {
-                if root == parent_path {
-                    break;
-                }
-            }
+    #[structopt(about = "Explains which ignore files and globs decide the fate of a single path")]
+    Explain(ExplainArgs),
 
-            dir = parent_path;
-        }
+    #[structopt(about = "Lists past nukes and the space they reclaimed, from the audit log")]
+    History(HistoryArgs),
+}
 
-        // Push parent gitignores onto gitignore_stack
-        for ignore in parent_gitignore.into_iter().rev() {
-            gitignore_tip = gitignore_tip.child(ignore);
+fn main() -> ExitCode {
+    // Parse args
+    // Argument errors are mapped to our own exit code rather than clap's default
+    let opt = match Opts::from_iter_safe(env::args_os()) {
+        Ok(opt) => opt,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            return ExitStatus::Error.into();
         }
+        Err(e) => {
+            println!("{}", e.message);
+            return ExitStatus::Success.into();
+        }
+    };
 
-        // Push parent gitnukes onto gitnuke_stack
-        for ignore in parent_gitnuke.into_iter().rev() {
-            gitnuke_tip = gitnuke_tip.child(ignore);
+    match run(opt) {
+        Ok(status) => status.into(),
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitStatus::Error.into()
         }
     }
+}
 
-    // Recursive job takes a path, checks if it's ignored, and recurses into subdirs if needed
-    // Return value is result for the path only. Sub-directories will run separately
-    // and return their own result.
-    let recursive_job =
-        |(mut gitignore_tip, mut gitnuke_tip, path): (ArcCactus<Gitignore>, ArcCactus<Gitignore>, PathBuf),
-         worker: &Worker<_>|
-         -> Option<Vec<PathBuf>> {
-            let mut job_ignores: Vec<_> = Default::default();
-
-            // Get iterator to directory children
-            let read_dir = fs::read_dir(&path).ok()?;
-
-            // Check for source control root
-            if path.join(".git").exists() || path.join(".hg").exists() {
-                // Reset ignore tip
-                gitignore_tip = global_ignore.clone();
-            }
+fn run(opt: Opts) -> anyhow::Result<ExitStatus> {
+    // Subcommands
+    if let Some(command) = &opt.command {
+        return match command {
+            Command::Cache(command) => cache::run(command).map(|_| ExitStatus::Success),
+            Command::Check(args) => check::run(args, &opt.scan, &opt.list, opt.print_errors, opt.units),
+            Command::Diff(args) => report::run(args, opt.units),
+            Command::Explain(args) => explain::run(args, &opt.scan),
+            Command::History(args) => audit::run(args, opt.units).map(|_| ExitStatus::Success),
+        };
+    }
 
-            // Add `.gitignore` patterns
-            if let Some(ignore) = build_ignore(&path, ".gitignore") {
-                gitignore_tip = gitignore_tip.child(ignore);
-            }
+    let start = Instant::now();
+    // Every error encountered is collected and summarised at the end of the run
+    let error_report = ErrorReport::new();
+
+    // Find everything ignored
+    let cache = if opt.scan.no_cache {
+        ScanCache::disabled()
+    } else {
+        ScanCache::load()
+    };
+    let starting_dirs = scan::starting_dirs(&opt.scan)?;
+    println!("🔍 scanning for targets from {:?}", starting_dirs);
+    let scanned = scan::scan(&opt.scan, &starting_dirs, cache, &error_report)?;
+    let mut final_ignore_paths = scanned.candidates;
+
+    // Save everything found, before any filtering, so runs can be compared
+    if let Some(report_path) = &opt.save_report {
+        SavedReport::new(&starting_dirs, &final_ignore_paths).save(report_path)?;
+    }
 
-            // Add `.gitnuke` patterns
-            if let Some(ignore) = build_ignore(&path, ".gitnuke") {
-                gitnuke_tip = gitnuke_tip.child(ignore);
+    // Summarise buckets, or keep only the selected buckets
+    let print_buckets = opt.group_by.is_some() && opt.bucket.is_empty();
+    if let Some(group_by) = opt.group_by {
+        if print_buckets && !opt.benchmark && !final_ignore_paths.is_empty() {
+            println!("  {:10} {:>8} {:>6}  Bucket", "Size", "Paths", "Repos");
+            for bucket in group_by.group(&final_ignore_paths, &starting_dirs) {
+                println!(
+                    "  {:10} {:>8} {:>6}  {}",
+                    opt.units.format(bucket.size),
+                    bucket.count,
+                    bucket.num_repos,
+                    bucket.key
+                );
             }
+        } else if !opt.bucket.is_empty() {
+            final_ignore_paths.retain(|candidate| opt.bucket.contains(&group_by.key(candidate, &starting_dirs)));
+        }
+    }
 
-            // Process each child in directory
-            for child in read_dir {
-                let result = || -> anyhow::Result<()> {
-                    let child_path = child.with_context(|| path_context!("fs::read_dir", &path))?.path();
-                    let child_meta =
-                        std::fs::metadata(&child_path).with_context(|| path_context!("fs::metadata", &child_path))?;
-
-                    // Test if child_path is ignored, whitelisted, or neither
-                    // Return first match that is either ignored or whitelisted
-                    let is_dir = child_meta.is_dir();
-                    let ignore_match = gitnuke_tip
-                        .vals()
-                        .chain(gitignore_tip.vals())
-                        .map(|i| i.matched(&child_path, is_dir))
-                        .find(|m| !m.is_none());
-
-                    // Handle ignored/whitelisted/neither
-                    match ignore_match {
-                        Some(m) => {
-                            //  ignored or whitelisted
-                            if opt.print_glob_matches {
-                                let glob = m.inner().unwrap();
-                                println!(
-                                    "Glob [{:?}] from Gitignore [{:?}] matched path [{:?}]",
-                                    glob.original(),
-                                    glob.from(),
-                                    child_path
-                                );
-                            }
-
-                            // Add ignores to the list. Do nothing if whitelisted
-                            if m.is_ignore() {
-                                job_ignores.push(child_path);
-                            } else {
-                                assert!(m.is_whitelist());
-                            }
-                        }
-                        None => {
-                            // No match, recurse into directories
-                            if is_dir {
-                                worker.push((gitignore_tip.clone(), gitnuke_tip.clone(), child_path));
-                            }
-                        }
-                    }
-
-                    // Child tested
-                    Ok(())
-                }();
+    // Sort, then limit once held back candidates are gone. What is listed is exactly what gets nuked.
+    let mut final_ignore_paths = listing::arrange(final_ignore_paths, &opt.list);
 
-                // Print error if child could not be checked
-                check_error(result);
-            }
-
-            // Return ignored paths for path
-            Some(job_ignores)
+    // Hold back anything catastrophic to nuke
+    if !opt.benchmark {
+        let rails = Rails {
+            starting_dirs: &starting_dirs,
+            home: dirs::home_dir().and_then(|home| home.canonicalize().ok()),
+            total_bytes: scanned.total_bytes,
+            args: &opt.safety,
         };
-
-    // Initialize data
-    println!("🔍 scanning for targets from [{:?}]", starting_dir);
-    let initial_data = vec![(gitignore_tip, gitnuke_tip, starting_dir)];
-
-    // Run recursive jobs
-    let ignored_paths: Vec<(usize, PathBuf)> = job_system::run_recursive_job(initial_data, recursive_job, num_threads)
-        .into_iter()
-        .flatten()
-        .enumerate()
-        .collect();
-
-    // Second recursive job to compute size of ignored directories
-    let recursive_dir_size_job = |(root_idx, path): (usize, PathBuf), worker: &Worker<_>| -> Option<(usize, u64)> {
-        // Get type of path
-        let path_meta = fs::metadata(&path).ok()?;
-
-        // If file, return result immediately
-        if path_meta.is_file() {
-            return Some((root_idx, path_meta.len()));
+        let mut refused = Vec::new();
+        final_ignore_paths.retain(|candidate| match rails.check(candidate) {
+            Some(refusal) => {
+                refused.push((candidate.clone(), refusal));
+                false
+            }
+            None => true,
+        });
+        if !refused.is_empty() {
+            println!("Refused, not nuked:");
+            for (candidate, refusal) in &refused {
+                println!("  {:10} {:?}", opt.units.format(candidate.size), candidate.path);
+                println!("  {:10}   {}", "", refusal);
+            }
+            println!();
         }
+    }
 
-        // Get director iterator
-        let read_dir = fs::read_dir(&path).ok()?;
-
-        // Iterate children
-        let mut files_size = 0;
-        for child in read_dir {
-            let result = || -> anyhow::Result<()> {
-                // Ignore errors
-                let child_path = child.with_context(|| path_context!("read_dir", &path))?.path();
-                let child_meta =
-                    fs::metadata(&child_path).with_context(|| path_context!("fs::metadata", &child_path))?;
-
-                // Accumualte file size
-                // Add directories to the worker
-                if child_meta.is_file() {
-                    files_size += child_meta.len();
-                } else {
-                    worker.push((root_idx, child_path));
+    // Leave alone anything a running process is using
+    // Both this and the busy check are repeated right before each deletion, see `Revalidator`
+    let busy_within = opt.busy_within.map(|minutes| Duration::from_secs(minutes * 60));
+    if !opt.benchmark {
+        let open_paths = OpenPaths::snapshot();
+        let (in_use, free): (Vec<_>, Vec<_>) = final_ignore_paths
+            .into_iter()
+            .partition(|candidate| !open_paths.users(&candidate.path).is_empty());
+        if !in_use.is_empty() {
+            println!("In use by running processes, not nuked:");
+            for candidate in &in_use {
+                println!("  {:10} {:?}", opt.units.format(candidate.size), candidate.path);
+                for user in open_paths.users(&candidate.path) {
+                    println!("  {:10}   {}", "", user);
                 }
-
-                Ok(())
-            }();
-            check_error(result);
+            }
+            println!();
         }
+        final_ignore_paths = free;
+
+        // Leave alone anything a build seems to be writing to
+        let busy_check = BusyCheck::new(busy_within);
+        let mut busy = Vec::new();
+        final_ignore_paths.retain(|candidate| {
+            let starting_dir = scan::starting_dir_of(&starting_dirs, &candidate.path).unwrap_or(&candidate.path);
+            match busy_check.check(candidate, starting_dir) {
+                Some(reason) => {
+                    busy.push((candidate.clone(), reason));
+                    false
+                }
+                None => true,
+            }
+        });
+        if !busy.is_empty() {
+            println!("Busy, not nuked:");
+            for (candidate, reason) in &busy {
+                println!("  {:10} {:?}", opt.units.format(candidate.size), candidate.path);
+                println!("  {:10}   {}", "", reason);
+            }
+            println!();
+        }
+    }
 
-        Some((root_idx, files_size))
-    };
-
-    // Compute path sizes
-    let dir_sizes = job_system::run_recursive_job(ignored_paths.clone(), recursive_dir_size_job, num_threads);
+    final_ignore_paths = listing::top(final_ignore_paths, &opt.list);
 
-    // Sum sizes
-    let mut ignore_path_sizes: Vec<u64> = Default::default();
-    ignore_path_sizes.resize(ignored_paths.len(), 0);
-    for (idx, size) in dir_sizes {
-        ignore_path_sizes[idx] += size;
+    // Only nuke as much as needed
+    if let Some(target) = opt.reclaim {
+        final_ignore_paths = listing::reclaim(final_ignore_paths, target.0);
+        let total: u64 = final_ignore_paths.iter().map(|c| c.size).sum();
+        if total < target.0 {
+            println!(
+                "Warning: only {} can be reclaimed, short of the {} requested",
+                opt.units.format(total),
+                opt.units.format(target.0)
+            );
+        }
     }
 
-    // Sort ignored paths by size
-    let final_ignore_paths: Vec<_> = ignored_paths
-        .into_iter()
-        .zip(ignore_path_sizes)
-        .map(|((_, path), size)| (path, size))
-        .filter(|(_, size)| *size >= opt.min_file_size)
-        .sorted_by_key(|kvp| kvp.1)
-        .collect();
-
     // No ignores found
     if final_ignore_paths.is_empty() {
         println!("No ignore paths to delete.");
-        return Ok(());
+        error_report.print_summary(opt.print_errors);
+        return Ok(error_report.exit_status(ExitStatus::Success));
     }
 
     // Print ignores
-    let mut total_bytes = 0;
-    for (path, bytes) in &final_ignore_paths {
-        total_bytes += bytes;
-        if !opt.benchmark {
-            println!("  {:10} {:?}", pretty_bytes(*bytes), path);
+    let total_bytes: u64 = final_ignore_paths.iter().map(|c| c.size).sum();
+    if !opt.benchmark && !print_buckets {
+        let (shown, others) = listing::collapse(&final_ignore_paths, &opt.list);
+        for candidate in shown {
+            if opt.scan.projects {
+                let kind = candidate
+                    .project
+                    .as_ref()
+                    .map(|p| p.kind.to_string())
+                    .unwrap_or_default();
+                println!(
+                    "  {:10} {:8} {:?}",
+                    opt.units.format(candidate.size),
+                    kind,
+                    candidate.path
+                );
+            } else {
+                println!("  {:10} {:?}", opt.units.format(candidate.size), candidate.path);
+            }
+        }
+        if let Some(others) = others {
+            println!(
+                "  {:10} ...and {} others smaller than {}",
+                opt.units.format(others.bytes),
+                others.count,
+                opt.units.format(opt.list.collapse_below.unwrap_or_default().0)
+            );
+        }
+    }
+
+    // Print totals for each project
+    if opt.scan.projects && !opt.benchmark && !print_buckets {
+        let mut project_sizes: HashMap<&Project, u64> = Default::default();
+        for candidate in &final_ignore_paths {
+            if let Some(project) = &candidate.project {
+                *project_sizes.entry(project).or_default() += candidate.size;
+            }
+        }
+        println!("\nProjects:");
+        for (project, bytes) in project_sizes
+            .into_iter()
+            .sorted_by_key(|(_, bytes)| std::cmp::Reverse(*bytes))
+        {
+            println!("  {:10} {:8} {:?}", opt.units.format(bytes), project.kind, project.root);
         }
     }
-    println!("Total Bytes: {}", total_bytes.to_formatted_string(&Locale::en));
+    // Print totals for each starting directory
+    if starting_dirs.len() > 1 && !opt.benchmark {
+        println!("\nStarting directories:");
+        for starting_dir in &starting_dirs {
+            let bytes: u64 = final_ignore_paths
+                .iter()
+                .filter(|c| c.path.starts_with(starting_dir))
+                .map(|c| c.size)
+                .sum();
+            println!("  {:10} {:?}", opt.units.format(bytes), starting_dir);
+        }
+    }
+    println!("Total Bytes: {}", opt.units.format(total_bytes));
     println!("Time: {:?}", start.elapsed());
 
     // Skip NUKE op in benchmark and dry-run modes
     if opt.benchmark || opt.dry_run {
-        return Ok(());
+        error_report.print_summary(opt.print_errors);
+        return Ok(error_report.exit_status(ExitStatus::CandidatesFound));
     }
 
     // Verify nuke
@@ -361,27 +380,19 @@ fn main() -> anyhow::Result<()> {
     const QUIT_STRING: &str = "QUIT";
 
     // Helper to remove either a file or a directory
-    let remove_path = |path: &std::path::Path| {
-        // Try to remove path
-        let result = || -> anyhow::Result<()> {
-            let meta = fs::metadata(&path).with_context(|| format!("{} {}", "fs::metadata", path.display()))?;
-
-            // Remove file or directory
-            if meta.is_file() {
-                std::fs::remove_file(&path).with_context(|| format!("{} {}", "fs::remove_file", path.display()))?;
-            } else {
-                std::fs::remove_dir_all(&path)
-                    .with_context(|| format!("{} {}", "fs::remove_dir_all", path.display()))?;
-            }
-
-            Ok(())
-        }();
-
-        // Always print removal errors
-        match result {
-            Ok(_) => (),
-            Err(e) => println!("Error: {}", e),
+    // Returns the error message if the path could not be removed entirely
+    let remove_path = |path: &std::path::Path| -> Option<String> {
+        let errors = delete::remove(path);
+        let message = errors.first().map(|first| match errors.len() {
+            1 => first.to_string(),
+            n => format!("{} (and {} more)", first, n - 1),
+        });
+
+        // Record removal errors. They are always printed in the summary.
+        for e in errors {
+            error_report.record(e);
         }
+        message
     };
 
     // Loop to get confirmation to nuke data or quit
@@ -390,21 +401,43 @@ fn main() -> anyhow::Result<()> {
         println!("Type {} to proceed, {} to quit:", NUKE_STRING, QUIT_STRING);
         let mut input = String::new();
 
-        std::io::stdin().read_line(&mut input)?;
+        // Treat end of input the same as quitting so piped runs never loop forever
+        let bytes_read = std::io::stdin().read_line(&mut input)?;
         let trimmed_input = input.trim();
         if trimmed_input == NUKE_STRING {
             println!("\n☢️☢️☢️ nuclear launch detected ☢️☢️☢️");
 
-            // Delete all the things
-            for (path, _) in final_ignore_paths {
-                remove_path(&path);
+            // Delete all the things, logging each outcome
+            // Failing to write the log never stops a nuke that has already started
+            let mut audit_log = AuditLog::open()
+                .map_err(|e| eprintln!("Warning: {:#}. Nothing will be logged.", e))
+                .ok();
+            // Anything that changed while the list was being read is skipped
+            let revalidator = Revalidator::new(&opt.scan, &starting_dirs, busy_within)?;
+            for candidate in final_ignore_paths {
+                let outcome = match revalidator.check(&candidate) {
+                    Some(change) => {
+                        println!("Skipped [{:?}]: {}", candidate.path, change);
+                        Outcome::Skipped(change.to_string())
+                    }
+                    None => remove_path(&candidate.path).map_or(Outcome::Removed, Outcome::Failed),
+                };
+                let starting_dir = scan::starting_dir_of(&starting_dirs, &candidate.path).unwrap_or(&candidate.path);
+                if let Some(Err(e)) = audit_log
+                    .as_mut()
+                    .map(|log| log.record(&candidate, starting_dir, outcome))
+                {
+                    eprintln!("Warning: failed to write audit log: {:#}", e);
+                    audit_log = None;
+                }
             }
 
             println!("☠️☠️☠️ nuclear deletion complete ☠️☠️☠️");
             break;
-        } else if trimmed_input.eq_ignore_ascii_case(QUIT_STRING) {
+        } else if bytes_read == 0 || trimmed_input.eq_ignore_ascii_case(QUIT_STRING) {
             println!("😇😇😇 Nuclear launch aborted. Thank you and have a nice day. 😇😇😇");
-            break;
+            error_report.print_summary(opt.print_errors);
+            return Ok(error_report.exit_status(ExitStatus::Aborted));
         } else {
             println!(
                 "Invalid input. Input was [{}] but must exactly match [{}] to irrevocably nuke. Please try again.",
@@ -413,29 +446,12 @@ fn main() -> anyhow::Result<()> {
         }
     }
 
-    // Mission accomplished
-    Ok(())
-}
-
-// Print u64 bytes value as a suffixed string
-fn pretty_bytes(orig_amount: u64) -> String {
-    let mut amount = orig_amount;
-    let mut order = 0;
-    while amount > 1000 {
-        amount /= 1000;
-        order += 1;
+    // Report errors and fail if anything could not be deleted
+    error_report.print_summary(opt.print_errors);
+    if error_report.count(ErrorKind::DeleteFailed) > 0 {
+        return Ok(error_report.exit_status(ExitStatus::PartialFailure));
     }
 
-    match order {
-        0 => format!("{} b", amount),
-        1 => format!("{} Kb", amount),
-        2 => format!("{} Mb", amount),
-        3 => format!("{} Gb", amount),
-        4 => format!("{} Tb", amount),
-        5 => format!("{} Pb", amount),
-        6 => format!("{} Exa", amount),
-        7 => format!("{} Zetta", amount),
-        8 => format!("{} Yotta", amount),
-        _ => format!("{}", orig_amount),
-    }
+    // Mission accomplished
+    Ok(error_report.exit_status(ExitStatus::Success))
 }
@@ -0,0 +1,164 @@
+use anyhow::anyhow;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::ffi::OsString;
+use std::path::Path;
+
+// Curated ignore patterns for a type of project
+// Patterns only apply to directories containing one of the marker files, and are
+// rooted at that directory exactly as if they were in a .gitignore next to the marker.
+pub struct Preset {
+    pub name: &'static str,
+
+    // Paths that identify a project. `*.ext` matches any file with that extension.
+    pub markers: &'static [&'static str],
+
+    pub patterns: &'static [&'static str],
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "rust",
+        markers: &["Cargo.toml"],
+        patterns: &["/target/"],
+    },
+    Preset {
+        name: "node",
+        markers: &["package.json"],
+        patterns: &["/node_modules/", "/.next/", "/.nuxt/", "/.parcel-cache/"],
+    },
+    Preset {
+        name: "python",
+        markers: &["pyproject.toml", "setup.py", "setup.cfg", "requirements.txt", "tox.ini"],
+        patterns: &[
+            "__pycache__/",
+            "*.py[cod]",
+            "/.pytest_cache/",
+            "/.mypy_cache/",
+            "/.tox/",
+            "/.venv/",
+            "/build/",
+            "/dist/",
+            "*.egg-info/",
+        ],
+    },
+    Preset {
+        name: "gradle",
+        markers: &[
+            "build.gradle",
+            "build.gradle.kts",
+            "settings.gradle",
+            "settings.gradle.kts",
+        ],
+        patterns: &["/.gradle/", "/build/"],
+    },
+    Preset {
+        name: "cmake",
+        markers: &["CMakeLists.txt"],
+        patterns: &["/build/", "/cmake-build-*/", "/CMakeFiles/", "/CMakeCache.txt"],
+    },
+    Preset {
+        name: "unity",
+        markers: &["ProjectSettings/ProjectVersion.txt"],
+        patterns: &["/Library/", "/Temp/", "/Obj/", "/Logs/", "/Build/", "/Builds/"],
+    },
+    Preset {
+        name: "unreal",
+        markers: &["*.uproject", "*.uplugin"],
+        patterns: &["/Binaries/", "/Intermediate/", "/Saved/", "/DerivedDataCache/"],
+    },
+];
+
+pub const PRESET_NAMES: &[&str] = &["rust", "node", "python", "gradle", "cmake", "unity", "unreal"];
+
+// Look up presets by name
+pub fn find_presets(names: &[String]) -> anyhow::Result<Vec<&'static Preset>> {
+    names
+        .iter()
+        .map(|name| {
+            PRESETS
+                .iter()
+                .find(|p| p.name == name.as_str())
+                .ok_or_else(|| anyhow!("Unknown preset [{}]", name))
+        })
+        .collect()
+}
+
+impl Preset {
+    // Test if `dir`, whose children are named `child_names`, contains one of this preset's marker files
+    // Only markers nested below a matching child, such as `ProjectSettings/ProjectVersion.txt`, touch the disk.
+    pub fn is_marked(&self, dir: &Path, child_names: &[OsString]) -> bool {
+        let has_child = |name: &str| child_names.iter().any(|n| n == name);
+        self.markers.iter().any(|marker| match marker.strip_prefix('*') {
+            Some(extension) => child_names.iter().any(|n| n.to_string_lossy().ends_with(extension)),
+            None => match marker.split_once('/') {
+                Some((first, _)) => has_child(first) && dir.join(marker).exists(),
+                None => has_child(marker),
+            },
+        })
+    }
+
+    // Build this preset's patterns rooted at `dir`
+    pub fn build(&self, dir: &Path) -> anyhow::Result<Gitignore> {
+        let mut builder = GitignoreBuilder::new(dir);
+        for pattern in self.patterns {
+            builder.add_line(None, pattern)?;
+        }
+        Ok(builder.build()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_match_presets() {
+        let names: Vec<_> = PRESETS.iter().map(|p| p.name).collect();
+        assert_eq!(names, PRESET_NAMES);
+    }
+
+    #[test]
+    fn presets_build() {
+        for preset in PRESETS {
+            let ignore = preset.build(Path::new("/project")).unwrap();
+            assert_eq!(ignore.num_ignores() as usize, preset.patterns.len());
+        }
+    }
+
+    #[test]
+    fn unknown_preset() {
+        assert!(find_presets(&["rust".to_owned(), "node".to_owned()]).is_ok());
+        assert!(find_presets(&["cobol".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn rust_preset_is_rooted() {
+        let preset = find_presets(&["rust".to_owned()]).unwrap()[0];
+        let ignore = preset.build(Path::new("/project")).unwrap();
+        assert!(ignore.matched("/project/target", true).is_ignore());
+        assert!(ignore.matched("/project/src/target", true).is_none());
+        assert!(ignore.matched("/project/target", false).is_none());
+    }
+
+    #[test]
+    fn markers_are_found_in_listing() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        let names = |names: &[&str]| -> Vec<OsString> { names.iter().map(OsString::from).collect() };
+        let preset = |name: &str| find_presets(&[name.to_owned()]).unwrap()[0];
+
+        // Markers are never looked up on disk when the listing lacks them
+        std::fs::write(dir.join("Cargo.toml"), "").unwrap();
+        assert!(!preset("rust").is_marked(dir, &names(&["src"])));
+        assert!(preset("rust").is_marked(dir, &names(&["Cargo.toml", "src"])));
+        assert!(preset("unreal").is_marked(dir, &names(&["Game.uproject"])));
+        assert!(!preset("unreal").is_marked(dir, &names(&["uproject"])));
+
+        // Nested markers are checked on disk below a matching child
+        assert!(!preset("unity").is_marked(dir, &names(&["ProjectSettings"])));
+        std::fs::create_dir(dir.join("ProjectSettings")).unwrap();
+        std::fs::write(dir.join("ProjectSettings").join("ProjectVersion.txt"), "").unwrap();
+        assert!(preset("unity").is_marked(dir, &names(&["ProjectSettings"])));
+        assert!(!preset("unity").is_marked(dir, &names(&["Assets"])));
+    }
+}
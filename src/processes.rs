@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// How a process uses a path
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Usage {
+    OpenFile,
+    WorkingDir,
+    Executable,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Usage::OpenFile => "open file",
+            Usage::WorkingDir => "working directory",
+            Usage::Executable => "executable",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Process using a path
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProcessUse {
+    pub pid: u32,
+    pub command: String,
+    pub usage: Usage,
+}
+
+impl fmt::Display for ProcessUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pid {} ({}) {}", self.pid, self.command, self.usage)
+    }
+}
+
+// Paths used by running processes, as of when the snapshot was taken
+// Deleting a directory out from under a running build or IDE causes confusing failures, so candidates
+// containing any of these paths are not nuked. Only Linux is supported. Elsewhere nothing is in use.
+#[derive(Default)]
+pub struct OpenPaths {
+    uses: BTreeMap<PathBuf, Vec<ProcessUse>>,
+}
+
+impl OpenPaths {
+    // Snapshot every process except this one
+    pub fn snapshot() -> OpenPaths {
+        if cfg!(target_os = "linux") {
+            read_proc(Path::new("/proc"), Some(std::process::id()))
+        } else {
+            Default::default()
+        }
+    }
+
+    // Processes using `path` or anything inside it, at most once per process and usage
+    pub fn users(&self, path: &Path) -> Vec<&ProcessUse> {
+        let mut users: Vec<&ProcessUse> = self
+            .uses
+            .range(path.to_owned()..)
+            .take_while(|(used, _)| used.starts_with(path))
+            .flat_map(|(_, uses)| uses)
+            .collect();
+        users.sort();
+        users.dedup();
+        users
+    }
+}
+
+// Read open files, working directories and executables from a procfs mounted at `proc_dir`
+// Processes owned by other users usually can't be inspected and are silently skipped.
+fn read_proc(proc_dir: &Path, skip_pid: Option<u32>) -> OpenPaths {
+    let mut open_paths = OpenPaths::default();
+    let entries = match std::fs::read_dir(proc_dir) {
+        Ok(entries) => entries,
+        Err(_) => return open_paths,
+    };
+
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) {
+            Some(pid) if Some(pid) != skip_pid => pid,
+            _ => continue,
+        };
+        let process_dir = entry.path();
+        let command = std::fs::read_to_string(process_dir.join("comm"))
+            .map(|comm| comm.trim_end().to_owned())
+            .unwrap_or_default();
+        let mut add = |path: PathBuf, usage: Usage| {
+            // Sockets, pipes and anonymous files aren't paths
+            if path.is_absolute() {
+                open_paths.uses.entry(path).or_default().push(ProcessUse {
+                    pid,
+                    command: command.clone(),
+                    usage,
+                });
+            }
+        };
+
+        if let Ok(cwd) = std::fs::read_link(process_dir.join("cwd")) {
+            add(cwd, Usage::WorkingDir);
+        }
+        if let Ok(exe) = std::fs::read_link(process_dir.join("exe")) {
+            add(exe, Usage::Executable);
+        }
+        if let Ok(fds) = std::fs::read_dir(process_dir.join("fd")) {
+            for fd in fds.flatten() {
+                if let Ok(path) = std::fs::read_link(fd.path()) {
+                    add(path, Usage::OpenFile);
+                }
+            }
+        }
+    }
+
+    open_paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_include_nested_paths() {
+        let mut open_paths = OpenPaths::default();
+        let cargo = ProcessUse {
+            pid: 7,
+            command: "cargo".to_owned(),
+            usage: Usage::OpenFile,
+        };
+        for path in ["/src/a/target/debug/app", "/src/a/target/.cargo-lock", "/src/a/targets"] {
+            open_paths
+                .uses
+                .entry(PathBuf::from(path))
+                .or_default()
+                .push(cargo.clone());
+        }
+
+        assert_eq!(open_paths.users(Path::new("/src/a/target")), vec![&cargo]);
+        assert_eq!(open_paths.users(Path::new("/src/a/targets")), vec![&cargo]);
+        assert!(open_paths.users(Path::new("/src/a/target/release")).is_empty());
+        assert!(open_paths.users(Path::new("/src/b")).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_files_are_found() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().canonicalize().unwrap();
+        let path = dir.join("held.txt");
+        let _file = std::fs::File::create(&path).unwrap();
+
+        let open_paths = read_proc(Path::new("/proc"), None);
+        let users = open_paths.users(&dir);
+        assert!(users
+            .iter()
+            .any(|u| u.pid == std::process::id() && u.usage == Usage::OpenFile));
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Type of project detected from a marker file
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+    Cargo,
+    Node,
+    CMake,
+    MsBuild,
+    Maven,
+}
+
+impl fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ProjectKind::Cargo => "cargo",
+            ProjectKind::Node => "node",
+            ProjectKind::CMake => "cmake",
+            ProjectKind::MsBuild => "msbuild",
+            ProjectKind::Maven => "maven",
+        };
+        // Pad so the type can be used as a column
+        f.pad(s)
+    }
+}
+
+// A project and the directory containing its marker file
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Project {
+    pub kind: ProjectKind,
+    pub root: PathBuf,
+}
+
+// Build output directory belonging to a project
+#[derive(Clone, Debug)]
+pub struct ProjectOutput {
+    pub project: Project,
+    pub path: PathBuf,
+}
+
+// Detect projects rooted at `dir` and return their existing output directories
+// `child_names` are the names of every entry in `dir`. Outputs are usually children of `dir`
+// but may be elsewhere, for example a cargo `build.target-dir`. `cargo_target_dir_env` is the value of
+// `CARGO_TARGET_DIR`, passed in so callers read the environment once.
+pub fn detect(dir: &Path, child_names: &[OsString], cargo_target_dir_env: Option<&OsStr>) -> Vec<ProjectOutput> {
+    let mut outputs: Vec<ProjectOutput> = Default::default();
+    let has_child = |name: &str| child_names.iter().any(|n| n == name);
+    let has_extension = |extensions: &[&str]| {
+        child_names.iter().any(|n| {
+            Path::new(n)
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+        })
+    };
+    let mut add = |kind: ProjectKind, path: PathBuf| {
+        if path.is_dir() {
+            outputs.push(ProjectOutput {
+                project: Project {
+                    kind,
+                    root: dir.to_owned(),
+                },
+                path,
+            });
+        }
+    };
+
+    // Cargo builds into `target`, unless configured otherwise
+    if has_child("Cargo.toml") {
+        if let Some(target_dir) = cargo_target_dir(dir, cargo_target_dir_env) {
+            add(ProjectKind::Cargo, target_dir);
+        }
+    }
+
+    // npm, yarn and pnpm all install into `node_modules`
+    if has_child("package.json") {
+        add(ProjectKind::Node, dir.join("node_modules"));
+    }
+
+    // Any child directory holding a `CMakeCache.txt` is a CMake build tree
+    if has_child("CMakeLists.txt") {
+        for name in child_names {
+            let child = dir.join(name);
+            if child.join("CMakeCache.txt").is_file() {
+                add(ProjectKind::CMake, child);
+            }
+        }
+    }
+
+    // Visual Studio solutions and .NET / C++ projects
+    // Their output names are common words, so a directory must also hold files only a build writes
+    let mut msbuild_names: Vec<&str> = Vec::new();
+    if has_extension(&["sln"]) {
+        msbuild_names.extend([".vs", "ipch", "x64", "Debug", "Release"]);
+    }
+    if has_extension(&["csproj", "fsproj", "vbproj", "vcxproj"]) {
+        msbuild_names.extend(["bin", "obj"]);
+    }
+    for name in msbuild_names {
+        let path = dir.join(name);
+        if has_msbuild_signature(&path, MSBUILD_SIGNATURE_DEPTH) {
+            add(ProjectKind::MsBuild, path);
+        }
+    }
+
+    // Maven builds into `target`
+    if has_child("pom.xml") {
+        add(ProjectKind::Maven, dir.join("target"));
+    }
+
+    outputs
+}
+
+// Endings of file names that only MSBuild and Visual Studio write, compared in lowercase
+// Covers debug symbols, object files, build logs, precompiled headers, restore output and IDE state.
+const MSBUILD_SIGNATURES: &[&str] = &[
+    ".pdb",
+    ".obj",
+    ".tlog",
+    ".ilk",
+    ".idb",
+    ".iobj",
+    ".ipdb",
+    ".ipch",
+    ".deps.json",
+    "project.assets.json",
+    ".suo",
+    "slnx.sqlite",
+];
+
+// How many levels of `dir` are searched for a signature, enough for `bin/Debug/net8.0/app.pdb`
+const MSBUILD_SIGNATURE_DEPTH: usize = 3;
+
+// Whether `dir`, or a directory up to `depth` levels into it, holds a file from `MSBUILD_SIGNATURES`
+// Symlinked directories aren't followed.
+fn has_msbuild_signature(dir: &Path, depth: usize) -> bool {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(_) => return false,
+    };
+    let mut subdirs = Vec::new();
+    for entry in read_dir.flatten() {
+        let name = entry.file_name().to_string_lossy().to_ascii_lowercase();
+        if MSBUILD_SIGNATURES.iter().any(|signature| name.ends_with(signature)) {
+            return true;
+        }
+        if depth > 1 && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            subdirs.push(entry.path());
+        }
+    }
+    subdirs.iter().any(|subdir| has_msbuild_signature(subdir, depth - 1))
+}
+
+// Cargo target directory for the package or workspace rooted at `dir`
+// Honors `CARGO_TARGET_DIR` and `build.target-dir` from `.cargo/config.toml` in `dir` or any parent.
+// Cargo resolves a relative `CARGO_TARGET_DIR` against the directory it runs in, assumed to be `dir`.
+// A target dir that is `dir` itself or one of its parents, such as `target-dir = "."`, would nuke the
+// sources, so None is returned instead.
+fn cargo_target_dir(dir: &Path, cargo_target_dir_env: Option<&OsStr>) -> Option<PathBuf> {
+    let target_dir = cargo_target_dir_env
+        .map(|target_dir| dir.join(target_dir))
+        .unwrap_or_else(|| configured_target_dir(dir));
+    let target_dir = fs::canonicalize(&target_dir).unwrap_or(target_dir);
+    if dir.starts_with(&target_dir) {
+        return None;
+    }
+    Some(target_dir)
+}
+
+// Target directory from the closest `.cargo/config.toml`, or `target` by default
+fn configured_target_dir(dir: &Path) -> PathBuf {
+    #[derive(Deserialize)]
+    struct CargoConfig {
+        build: Option<BuildConfig>,
+    }
+
+    #[derive(Deserialize)]
+    struct BuildConfig {
+        #[serde(rename = "target-dir")]
+        target_dir: Option<PathBuf>,
+    }
+
+    // Closest config wins. Relative paths are relative to the directory containing `.cargo`.
+    for ancestor in dir.ancestors() {
+        for filename in &["config.toml", "config"] {
+            let config_path = ancestor.join(".cargo").join(filename);
+            let target_dir = fs::read_to_string(&config_path)
+                .ok()
+                .and_then(|contents| toml::from_str::<CargoConfig>(&contents).ok())
+                .and_then(|config| config.build)
+                .and_then(|build| build.target_dir);
+            if let Some(target_dir) = target_dir {
+                return ancestor.join(target_dir);
+            }
+        }
+    }
+
+    dir.join("target")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<OsString> {
+        names.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn missing_outputs_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let outputs = detect(
+            dir.path(),
+            &names(&["Cargo.toml", "package.json", "pom.xml", "foo.sln"]),
+            None,
+        );
+        assert!(outputs.is_empty());
+    }
+
+    #[test]
+    fn detects_outputs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        fs::create_dir_all(dir.join("node_modules")).unwrap();
+        fs::create_dir_all(dir.join("obj")).unwrap();
+        fs::write(dir.join("obj").join("project.assets.json"), "").unwrap();
+        fs::create_dir_all(dir.join("cmake-build-debug")).unwrap();
+        fs::write(dir.join("cmake-build-debug").join("CMakeCache.txt"), "").unwrap();
+
+        let child_names = names(&[
+            "package.json",
+            "node_modules",
+            "app.csproj",
+            "obj",
+            "CMakeLists.txt",
+            "cmake-build-debug",
+        ]);
+        let mut outputs: Vec<_> = detect(dir, &child_names, None)
+            .into_iter()
+            .map(|o| (o.project.kind, o.path.strip_prefix(dir).unwrap().to_owned()))
+            .collect();
+        outputs.sort();
+        assert_eq!(
+            outputs,
+            vec![
+                (ProjectKind::Node, PathBuf::from("node_modules")),
+                (ProjectKind::CMake, PathBuf::from("cmake-build-debug")),
+                (ProjectKind::MsBuild, PathBuf::from("obj")),
+            ]
+        );
+    }
+
+    #[test]
+    fn msbuild_outputs_need_a_signature() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        for (path, content) in [
+            ("Release/notes.txt", ""),
+            ("Debug/trace.log", ""),
+            ("x64/Debug/app.tlog/CL.command.1.tlog", ""),
+            ("bin/Debug/net8.0/App.PDB", ""),
+            ("obj/readme.md", ""),
+            ("ipch/deep/er/still/x.ipch", ""),
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let child_names = names(&["app.sln", "app.csproj", "Release", "Debug", "x64", "bin", "obj", "ipch"]);
+        let mut outputs: Vec<_> = detect(dir, &child_names, None)
+            .into_iter()
+            .map(|o| o.path.strip_prefix(dir).unwrap().to_owned())
+            .collect();
+        outputs.sort();
+
+        // Directories merely named like outputs, or with signatures too deep inside, are kept
+        assert_eq!(outputs, vec![PathBuf::from("bin"), PathBuf::from("x64")]);
+    }
+
+    #[test]
+    fn cargo_config_target_dir() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().canonicalize().unwrap();
+        let foo = dir.join("crates").join("foo");
+        fs::create_dir_all(dir.join(".cargo")).unwrap();
+        fs::create_dir_all(dir.join("out")).unwrap();
+        fs::create_dir_all(&foo).unwrap();
+        fs::write(
+            dir.join(".cargo").join("config.toml"),
+            "[build]\ntarget-dir = \"out\"\n",
+        )
+        .unwrap();
+        assert_eq!(cargo_target_dir(&foo, None), Some(dir.join("out")));
+
+        // The environment wins, relative to the project
+        assert_eq!(
+            cargo_target_dir(&foo, Some(OsStr::new("build"))),
+            Some(foo.join("build"))
+        );
+        assert_eq!(
+            cargo_target_dir(&foo, Some(OsStr::new("../../out"))),
+            Some(dir.join("out"))
+        );
+    }
+
+    #[test]
+    fn source_dirs_are_never_target_dirs() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path().canonicalize().unwrap();
+        let foo = dir.join("foo");
+        fs::create_dir_all(foo.join(".cargo")).unwrap();
+        fs::write(foo.join(".cargo").join("config.toml"), "[build]\ntarget-dir = \".\"\n").unwrap();
+
+        assert_eq!(cargo_target_dir(&foo, None), None);
+        assert_eq!(cargo_target_dir(&foo, Some(OsStr::new(".."))), None);
+        assert_eq!(cargo_target_dir(&foo, Some(dir.as_os_str())), None);
+        assert!(detect(&foo, &names(&["Cargo.toml"]), None).is_empty());
+    }
+}
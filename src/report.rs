@@ -0,0 +1,234 @@
+use crate::exit_code::ExitStatus;
+use crate::scan::Candidate;
+use crate::units::Units;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DiffArgs {
+    #[structopt(parse(from_os_str), help = "Report saved by an earlier run with --save-report")]
+    pub old: PathBuf,
+
+    #[structopt(parse(from_os_str), help = "Report saved by a later run with --save-report")]
+    pub new: PathBuf,
+}
+
+// Ignored path and its size at the time of the scan
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportEntry {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+// Snapshot of a scan, see `--save-report`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedReport {
+    pub starting_dirs: Vec<PathBuf>,
+
+    // Seconds since the unix epoch
+    pub created: u64,
+
+    pub candidates: Vec<ReportEntry>,
+}
+
+impl SavedReport {
+    pub fn new(starting_dirs: &[PathBuf], candidates: &[Candidate]) -> SavedReport {
+        SavedReport {
+            starting_dirs: starting_dirs.to_vec(),
+            created: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            candidates: candidates
+                .iter()
+                .map(|c| ReportEntry {
+                    path: c.path.clone(),
+                    bytes: c.size,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)? + "\n";
+        fs::write(path, json).with_context(|| format!("Failed to write report [{}]", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<SavedReport> {
+        let json = fs::read(path).with_context(|| format!("Failed to read report [{}]", path.display()))?;
+        serde_json::from_slice(&json).with_context(|| format!("Failed to parse report [{}]", path.display()))
+    }
+}
+
+// How an ignored path changed between two reports
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Appeared {
+        path: PathBuf,
+        bytes: u64,
+    },
+    Disappeared {
+        path: PathBuf,
+        bytes: u64,
+    },
+    Resized {
+        path: PathBuf,
+        old_bytes: u64,
+        new_bytes: u64,
+    },
+}
+
+impl Change {
+    // Signed change in bytes
+    pub fn delta(&self) -> i128 {
+        match self {
+            Change::Appeared { bytes, .. } => *bytes as i128,
+            Change::Disappeared { bytes, .. } => -(*bytes as i128),
+            Change::Resized {
+                old_bytes, new_bytes, ..
+            } => *new_bytes as i128 - *old_bytes as i128,
+        }
+    }
+}
+
+// Every path that appeared, disappeared or changed size, largest change first
+pub fn diff(old: &SavedReport, new: &SavedReport) -> Vec<Change> {
+    let old_sizes: HashMap<&Path, u64> = old.candidates.iter().map(|e| (e.path.as_path(), e.bytes)).collect();
+    let new_sizes: HashMap<&Path, u64> = new.candidates.iter().map(|e| (e.path.as_path(), e.bytes)).collect();
+
+    let mut changes: Vec<Change> = Default::default();
+    for entry in &new.candidates {
+        match old_sizes.get(entry.path.as_path()) {
+            None => changes.push(Change::Appeared {
+                path: entry.path.clone(),
+                bytes: entry.bytes,
+            }),
+            Some(&old_bytes) if old_bytes != entry.bytes => changes.push(Change::Resized {
+                path: entry.path.clone(),
+                old_bytes,
+                new_bytes: entry.bytes,
+            }),
+            Some(_) => (),
+        }
+    }
+    for entry in &old.candidates {
+        if !new_sizes.contains_key(entry.path.as_path()) {
+            changes.push(Change::Disappeared {
+                path: entry.path.clone(),
+                bytes: entry.bytes,
+            });
+        }
+    }
+
+    changes.sort_by_key(|c| std::cmp::Reverse(c.delta().abs()));
+    changes
+}
+
+// Print the difference between two saved reports
+pub fn run(args: &DiffArgs, units: Units) -> anyhow::Result<ExitStatus> {
+    let old = SavedReport::load(&args.old)?;
+    let new = SavedReport::load(&args.new)?;
+    if old.starting_dirs != new.starting_dirs {
+        println!(
+            "Warning: reports were scanned from different directories {:?} and {:?}",
+            old.starting_dirs, new.starting_dirs
+        );
+    }
+
+    let changes = diff(&old, &new);
+    if changes.is_empty() {
+        println!("No ignored paths changed.");
+        return Ok(ExitStatus::Success);
+    }
+
+    for change in &changes {
+        match change {
+            Change::Appeared { path, bytes } => {
+                println!("  appeared     {:>11} {:?}", units.format_delta(*bytes as i128), path)
+            }
+            Change::Disappeared { path, bytes } => {
+                println!("  disappeared  {:>11} {:?}", format!("-{}", units.format(*bytes)), path)
+            }
+            Change::Resized { path, .. } if change.delta() > 0 => {
+                println!("  grew         {:>11} {:?}", units.format_delta(change.delta()), path)
+            }
+            Change::Resized { path, .. } => {
+                println!("  shrank       {:>11} {:?}", units.format_delta(change.delta()), path)
+            }
+        }
+    }
+
+    let old_total: u64 = old.candidates.iter().map(|e| e.bytes).sum();
+    let new_total: u64 = new.candidates.iter().map(|e| e.bytes).sum();
+    println!(
+        "Total: {} -> {} ({})",
+        units.format(old_total),
+        units.format(new_total),
+        units.format_delta(new_total as i128 - old_total as i128)
+    );
+
+    Ok(ExitStatus::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(entries: &[(&str, u64)]) -> SavedReport {
+        SavedReport {
+            starting_dirs: vec![PathBuf::from("/src")],
+            created: 0,
+            candidates: entries
+                .iter()
+                .map(|(path, bytes)| ReportEntry {
+                    path: PathBuf::from(path),
+                    bytes: *bytes,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn diff_reports() {
+        let old = report(&[("/src/a/target", 100), ("/src/b/target", 50), ("/src/c/target", 7)]);
+        let new = report(&[
+            ("/src/a/target", 1000),
+            ("/src/c/target", 7),
+            ("/src/d/node_modules", 20),
+        ]);
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Change::Resized {
+                    path: PathBuf::from("/src/a/target"),
+                    old_bytes: 100,
+                    new_bytes: 1000,
+                },
+                Change::Disappeared {
+                    path: PathBuf::from("/src/b/target"),
+                    bytes: 50,
+                },
+                Change::Appeared {
+                    path: PathBuf::from("/src/d/node_modules"),
+                    bytes: 20,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn report_round_trip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("report.json");
+        let saved = report(&[("/src/a/target", 100)]);
+        saved.save(&path).unwrap();
+        let loaded = SavedReport::load(&path).unwrap();
+        assert_eq!(loaded.candidates, saved.candidates);
+        assert!(diff(&saved, &loaded).is_empty());
+    }
+}
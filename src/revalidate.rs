@@ -0,0 +1,313 @@
+use crate::busy::{BusyCheck, BusyReason};
+use crate::explain::{self, Verdict};
+use crate::ignore_stack::{is_vcs_root, IgnoreStacks};
+use crate::processes::{OpenPaths, ProcessUse};
+use crate::scan::{self, Candidate, ScanArgs};
+use itertools::Itertools;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Snapshots of running processes older than this are taken again before the next check
+// Taking one reads every process, which is too slow to repeat for each of thousands of candidates.
+const OPEN_PATHS_MAX_AGE: Duration = Duration::from_secs(1);
+
+// Identity of a path, without following symlinks
+// A directory swapped for a symlink, or deleted and recreated, has a different identity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FileId {
+    is_dir: bool,
+    is_symlink: bool,
+
+    // Device and inode. Always zero where unavailable, so only the type is compared.
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    pub fn of(path: &Path) -> io::Result<FileId> {
+        let meta = fs::symlink_metadata(path)?;
+        #[cfg(unix)]
+        let (dev, ino) = {
+            use std::os::unix::fs::MetadataExt;
+            (meta.dev(), meta.ino())
+        };
+        #[cfg(not(unix))]
+        let (dev, ino) = (0, 0);
+        Ok(FileId {
+            is_dir: meta.is_dir(),
+            is_symlink: meta.file_type().is_symlink(),
+            dev,
+            ino,
+        })
+    }
+}
+
+// How a candidate changed since it was scanned
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change {
+    Vanished,
+
+    // Replaced by a different file, directory or symlink
+    Replaced,
+
+    // Its ignore files no longer match it
+    NoLongerIgnored,
+
+    // It, or the ancestor containing it, is now whitelisted
+    Whitelisted(PathBuf),
+
+    // It is now, or now contains, a source control root, such as a checkout cloned into it
+    VcsRoot(PathBuf),
+
+    // A build started writing to it
+    Busy(BusyReason),
+
+    // A running process started using it
+    InUse(Vec<ProcessUse>),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Vanished => write!(f, "vanished since the scan"),
+            Change::Replaced => write!(f, "replaced since the scan"),
+            Change::NoLongerIgnored => write!(f, "no longer ignored"),
+            Change::Whitelisted(path) => write!(f, "now whitelisted by [{:?}]", path),
+            Change::VcsRoot(path) => write!(f, "now contains a source control root [{:?}]", path),
+            Change::Busy(reason) => write!(f, "now busy, {}", reason),
+            Change::InUse(users) => write!(f, "now in use by {}", users.iter().join(", ")),
+        }
+    }
+}
+
+// Checks candidates again right before they're nuked
+// Listing candidates and waiting for confirmation can take a long time, during which paths can be
+// replaced, ignore files edited and repositories cloned. Ignore files are read again from disk for
+// every check. Files tracked despite being ignored are not detected, since the index is never read,
+// but a `.git` or `.hg` appearing anywhere inside a candidate is. So are builds and processes that
+// started using a candidate, see `BusyCheck` and `OpenPaths`.
+pub struct Revalidator<'a> {
+    args: &'a ScanArgs,
+    starting_stacks: Vec<(PathBuf, IgnoreStacks)>,
+    busy_check: BusyCheck,
+
+    // Latest snapshot of running processes and when it was taken
+    open_paths: RefCell<(Instant, OpenPaths)>,
+}
+
+impl<'a> Revalidator<'a> {
+    // `busy_within` is the same as for the `BusyCheck` run before listing candidates
+    pub fn new(
+        args: &'a ScanArgs,
+        starting_dirs: &[PathBuf],
+        busy_within: Option<Duration>,
+    ) -> anyhow::Result<Revalidator<'a>> {
+        let starting_stacks = starting_dirs
+            .iter()
+            .map(|dir| Ok((dir.clone(), scan::starting_stacks_for(args, dir, false)?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Revalidator {
+            args,
+            starting_stacks,
+            busy_check: BusyCheck::new(busy_within),
+            open_paths: RefCell::new((Instant::now(), OpenPaths::snapshot())),
+        })
+    }
+
+    // How `candidate` changed since it was scanned, if at all
+    pub fn check(&self, candidate: &Candidate) -> Option<Change> {
+        // Same file, directory or symlink as scanned
+        match FileId::of(&candidate.path) {
+            Err(_) => return Some(Change::Vanished),
+            Ok(id) if Some(id) != candidate.id => return Some(Change::Replaced),
+            Ok(_) => (),
+        }
+
+        // Still ignored, evaluated exactly like the scan did
+        let (starting_dir, stacks) = match self
+            .starting_stacks
+            .iter()
+            .find(|(dir, _)| candidate.path.starts_with(dir))
+        {
+            Some(found) => found,
+            None => return Some(Change::NoLongerIgnored),
+        };
+        let verdict = explain::evaluate(starting_dir, stacks.clone(), &candidate.path, self.args, false);
+        match verdict {
+            Err(_) => return Some(Change::Vanished),
+            Ok(Verdict::Nuke(_)) => (),
+            Ok(Verdict::Whitelisted(path)) => return Some(Change::Whitelisted(path)),
+            // Outputs outside their own project aren't detected by the evaluation
+            Ok(Verdict::Untouched) if candidate.glob.is_none() && candidate.project.is_some() => (),
+            Ok(Verdict::Untouched) => return Some(Change::NoLongerIgnored),
+            // The scan skipped these with the same arguments, so this only happens if the two disagree
+            Ok(Verdict::Excluded(_)) | Ok(Verdict::TooDeep(_)) => return Some(Change::NoLongerIgnored),
+        }
+        if let Some(vcs_root) = find_vcs_root(&candidate.path) {
+            return Some(Change::VcsRoot(vcs_root));
+        }
+
+        // Not being built or used, checked last so as little time as possible passes before deletion
+        if let Some(reason) = self.busy_check.check(candidate, starting_dir) {
+            return Some(Change::Busy(reason));
+        }
+        let mut open_paths = self.open_paths.borrow_mut();
+        if open_paths.0.elapsed() > OPEN_PATHS_MAX_AGE {
+            *open_paths = (Instant::now(), OpenPaths::snapshot());
+        }
+        let users = open_paths.1.users(&candidate.path);
+        if !users.is_empty() {
+            return Some(Change::InUse(users.into_iter().cloned().collect()));
+        }
+        None
+    }
+}
+
+// First source control root at or below `path`, without following symlinks
+// Unreadable directories are skipped here and will fail to be removed instead.
+fn find_vcs_root(path: &Path) -> Option<PathBuf> {
+    let mut dirs = vec![path.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        if !fs::symlink_metadata(&dir).is_ok_and(|meta| meta.is_dir()) {
+            continue;
+        }
+        if is_vcs_root(&dir) {
+            return Some(dir);
+        }
+        if let Ok(read_dir) = fs::read_dir(&dir) {
+            dirs.extend(read_dir.flatten().map(|entry| entry.path()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn scanned(path: &Path) -> Candidate {
+        Candidate {
+            glob: Some("target/".to_owned()),
+            id: FileId::of(path).ok(),
+            ..Candidate::new(path.to_owned())
+        }
+    }
+
+    #[test]
+    fn changes_since_scan() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+
+        let args = ScanArgs::from_iter(&["test"]);
+        let starting_dirs = vec![root.clone()];
+        let revalidator = Revalidator::new(&args, &starting_dirs, None).unwrap();
+        let candidate = scanned(&root.join("target"));
+        assert_eq!(revalidator.check(&candidate), None);
+
+        // Ignore files are read again
+        fs::write(root.join(".gitnuke"), "!/target\n").unwrap();
+        assert_eq!(
+            revalidator.check(&candidate),
+            Some(Change::Whitelisted(root.join("target")))
+        );
+        fs::write(root.join(".gitnuke"), "").unwrap();
+        fs::write(root.join(".gitignore"), "").unwrap();
+        assert_eq!(revalidator.check(&candidate), Some(Change::NoLongerIgnored));
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+
+        // A repository cloned inside it
+        fs::create_dir_all(root.join("target/deps/clone/.git")).unwrap();
+        assert_eq!(
+            revalidator.check(&candidate),
+            Some(Change::VcsRoot(root.join("target/deps/clone")))
+        );
+        fs::remove_dir_all(root.join("target/deps")).unwrap();
+        fs::create_dir(root.join("target/.hg")).unwrap();
+        assert_eq!(
+            revalidator.check(&candidate),
+            Some(Change::VcsRoot(root.join("target")))
+        );
+        fs::remove_dir(root.join("target/.hg")).unwrap();
+        assert_eq!(revalidator.check(&candidate), None);
+
+        // A directory swapped for a symlink to somewhere precious
+        fs::remove_dir(root.join("target")).unwrap();
+        fs::create_dir(root.join("precious")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("precious"), root.join("target")).unwrap();
+        #[cfg(not(unix))]
+        fs::write(root.join("target"), "").unwrap();
+        assert_eq!(revalidator.check(&candidate), Some(Change::Replaced));
+
+        fs::remove_file(root.join("target")).unwrap();
+        assert_eq!(revalidator.check(&candidate), Some(Change::Vanished));
+    }
+
+    #[test]
+    fn builds_started_since_scan() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path().canonicalize().unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(
+            root.join(".gitignore"),
+            "target/
+",
+        )
+        .unwrap();
+        fs::write(root.join(".gitnuke"), "# lock: .building\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+
+        let args = ScanArgs::from_iter(&["test"]);
+        let starting_dirs = vec![root.clone()];
+        let candidate = scanned(&root.join("target"));
+        assert_eq!(
+            Revalidator::new(&args, &starting_dirs, None).unwrap().check(&candidate),
+            None
+        );
+
+        // A declared lock file appeared
+        fs::write(root.join(".building"), "").unwrap();
+        assert_eq!(
+            Revalidator::new(&args, &starting_dirs, None).unwrap().check(&candidate),
+            Some(Change::Busy(BusyReason::Declared {
+                lock: root.join(".building"),
+                gitnuke: root.join(".gitnuke"),
+            }))
+        );
+        fs::remove_file(root.join(".building")).unwrap();
+
+        // Recently written to
+        fs::write(root.join("target/app"), "").unwrap();
+        assert!(matches!(
+            Revalidator::new(&args, &starting_dirs, Some(Duration::from_secs(60)))
+                .unwrap()
+                .check(&candidate),
+            Some(Change::Busy(BusyReason::Modified(_)))
+        ));
+
+        // A process started working inside it
+        #[cfg(target_os = "linux")]
+        {
+            let mut child = std::process::Command::new("sleep")
+                .arg("30")
+                .current_dir(root.join("target"))
+                .spawn()
+                .unwrap();
+            let change = Revalidator::new(&args, &starting_dirs, None).unwrap().check(&candidate);
+            child.kill().unwrap();
+            child.wait().unwrap();
+            match change {
+                Some(Change::InUse(users)) => assert!(users.iter().any(|user| user.pid == child.id())),
+                change => panic!("Unexpected change {:?}", change),
+            }
+        }
+    }
+}
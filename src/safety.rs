@@ -0,0 +1,242 @@
+use crate::ignore_stack::IgnoreSource;
+use crate::scan::Candidate;
+use crate::units::ByteSize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+// Flags that let through candidates held back by a safety rail
+#[derive(StructOpt, Debug)]
+pub struct SafetyArgs {
+    #[structopt(
+        long,
+        help = "Nukes candidates that make up at least --large-fraction of the scanned tree. They are held back by default"
+    )]
+    pub allow_large: bool,
+
+    #[structopt(
+        long,
+        default_value = "0.9",
+        parse(try_from_str = parse_fraction),
+        help = "Share of the scanned tree, between 0 and 1, from which a candidate needs --allow-large"
+    )]
+    pub large_fraction: f64,
+
+    #[structopt(
+        long,
+        default_value = "100MB",
+        help = "Candidates smaller than this never need --allow-large, such as 0 or 1GiB"
+    )]
+    pub large_min_size: ByteSize,
+
+    #[structopt(
+        long,
+        help = "Nukes candidates matched by the global gitignore. They are held back by default"
+    )]
+    pub allow_global: bool,
+}
+
+// Why a candidate is never nuked, or not without an extra flag
+#[derive(Debug, PartialEq)]
+pub enum Refusal {
+    StartingDir,
+    Home,
+    FilesystemRoot,
+    ContainsVcsRoot,
+
+    // Share of the scanned tree, see `--allow-large`
+    Large(f64),
+
+    // See `--allow-global`
+    GlobalIgnore,
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::StartingDir => write!(f, "starting directory, never nuked"),
+            Refusal::Home => write!(f, "home directory, never nuked"),
+            Refusal::FilesystemRoot => write!(f, "filesystem root or mount point, never nuked"),
+            Refusal::ContainsVcsRoot => write!(f, "contains a source control root, never nuked"),
+            Refusal::Large(fraction) => write!(
+                f,
+                "{:.0}% of the scanned tree, nuked only with --allow-large",
+                fraction * 100.0
+            ),
+            Refusal::GlobalIgnore => write!(f, "matched by the global gitignore, nuked only with --allow-global"),
+        }
+    }
+}
+
+// Safety rails applied to every candidate before nuking
+pub struct Rails<'a> {
+    pub starting_dirs: &'a [PathBuf],
+    pub home: Option<PathBuf>,
+
+    // Size of everything scanned, see `Scanned::total_bytes`
+    pub total_bytes: u64,
+
+    pub args: &'a SafetyArgs,
+}
+
+impl Rails<'_> {
+    // Reason `candidate` must be held back, if any
+    pub fn check(&self, candidate: &Candidate) -> Option<Refusal> {
+        let path = &candidate.path;
+        if is_filesystem_root(path) {
+            Some(Refusal::FilesystemRoot)
+        } else if self.starting_dirs.iter().any(|dir| dir == path) {
+            Some(Refusal::StartingDir)
+        } else if self.home.as_deref().is_some_and(|home| home.starts_with(path)) {
+            Some(Refusal::Home)
+        } else if candidate.contains_vcs_root {
+            Some(Refusal::ContainsVcsRoot)
+        } else if candidate.source == Some(IgnoreSource::Global) && !self.args.allow_global {
+            Some(Refusal::GlobalIgnore)
+        } else {
+            let fraction = candidate.size as f64 / self.total_bytes.max(1) as f64;
+            let large = fraction >= self.args.large_fraction && candidate.size >= self.args.large_min_size.0;
+            Some(Refusal::Large(fraction)).filter(|_| large && !self.args.allow_large)
+        }
+    }
+}
+
+// Share between 0 and 1 inclusive, such as `0.9`
+fn parse_fraction(s: &str) -> anyhow::Result<f64> {
+    let fraction: f64 = s.parse()?;
+    if !(0.0..=1.0).contains(&fraction) {
+        anyhow::bail!("[{}] is not between 0 and 1", s);
+    }
+    Ok(fraction)
+}
+
+// Root of a filesystem, such as `/`, `C:\` or a mount point like `/mnt/data`
+fn is_filesystem_root(path: &Path) -> bool {
+    let parent = match path.parent() {
+        Some(parent) => parent,
+        None => return true,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(meta), Ok(parent_meta)) = (std::fs::symlink_metadata(path), std::fs::metadata(parent)) {
+            return meta.is_dir() && meta.dev() != parent_meta.dev();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = parent;
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_args() -> SafetyArgs {
+        SafetyArgs::from_iter(&["test"])
+    }
+
+    fn candidate(path: &str, size: u64) -> Candidate {
+        Candidate {
+            size,
+            ..Candidate::new(PathBuf::from(path))
+        }
+    }
+
+    #[test]
+    fn refusals() {
+        let starting_dirs = vec![PathBuf::from("/home/me/code")];
+        let args = default_args();
+        let rails = Rails {
+            starting_dirs: &starting_dirs,
+            home: Some(PathBuf::from("/home/me")),
+            total_bytes: 1000,
+            args: &args,
+        };
+
+        assert_eq!(rails.check(&candidate("/home/me/code", 1)), Some(Refusal::StartingDir));
+        assert_eq!(rails.check(&candidate("/home/me", 1)), Some(Refusal::Home));
+        assert_eq!(rails.check(&candidate("/home", 1)), Some(Refusal::Home));
+        assert_eq!(rails.check(&candidate("/", 1)), Some(Refusal::FilesystemRoot));
+        assert_eq!(rails.check(&candidate("/home/me/code/a/target", 100)), None);
+
+        let nested_checkout = Candidate {
+            contains_vcs_root: true,
+            ..candidate("/home/me/code/vendor", 1)
+        };
+        assert_eq!(rails.check(&nested_checkout), Some(Refusal::ContainsVcsRoot));
+    }
+
+    #[test]
+    fn fractions() {
+        assert_eq!(parse_fraction("0").unwrap(), 0.0);
+        assert_eq!(parse_fraction("0.9").unwrap(), 0.9);
+        assert_eq!(parse_fraction("1").unwrap(), 1.0);
+        for invalid in &["NaN", "-0.1", "1.5", "inf", "90%"] {
+            assert!(parse_fraction(invalid).is_err(), "{}", invalid);
+        }
+        assert!(SafetyArgs::from_iter_safe(&["test", "--large-fraction", "NaN"]).is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn mount_points_are_filesystem_roots() {
+        assert!(is_filesystem_root(Path::new("/")));
+        if Path::new("/proc/self").exists() {
+            assert!(is_filesystem_root(Path::new("/proc")));
+        }
+        assert!(!is_filesystem_root(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("src").as_path()
+        ));
+    }
+
+    #[test]
+    fn overridable_refusals() {
+        let starting_dirs = vec![PathBuf::from("/src")];
+        let global = Candidate {
+            source: Some(IgnoreSource::Global),
+            ..candidate("/src/a.swp", 1)
+        };
+        let large = candidate("/src/target", 950_000_000);
+
+        let args = default_args();
+        let rails = Rails {
+            starting_dirs: &starting_dirs,
+            home: None,
+            total_bytes: 1_000_000_000,
+            args: &args,
+        };
+        assert_eq!(rails.check(&global), Some(Refusal::GlobalIgnore));
+        assert_eq!(rails.check(&large), Some(Refusal::Large(0.95)));
+
+        // Small trees are never large, however much of them a candidate is
+        let small = candidate("/src/target", 950);
+        let small_rails = Rails {
+            total_bytes: 1000,
+            ..rails
+        };
+        assert_eq!(small_rails.check(&small), None);
+
+        let args = SafetyArgs {
+            large_fraction: 0.5,
+            large_min_size: ByteSize(0),
+            ..default_args()
+        };
+        let rails = Rails {
+            args: &args,
+            ..small_rails
+        };
+        assert_eq!(rails.check(&candidate("/src/target", 600)), Some(Refusal::Large(0.6)));
+        assert_eq!(rails.check(&candidate("/src/target", 400)), None);
+
+        let args = SafetyArgs {
+            allow_large: true,
+            allow_global: true,
+            ..default_args()
+        };
+        let rails = Rails { args: &args, ..rails };
+        assert_eq!(rails.check(&global), None);
+        assert_eq!(rails.check(&large), None);
+    }
+}
@@ -0,0 +1,652 @@
+use crate::cache::ScanCache;
+use crate::errors::{ErrorReport, Operation, PathError};
+use crate::explain::{self, ChildAction, Verdict};
+use crate::ignore_stack::{is_vcs_root, IgnoreSource, IgnoreStacks};
+use crate::job_system::{self, JobError};
+use crate::presets::{self, PRESET_NAMES};
+use crate::projects::{self, Project, ProjectOutput};
+use crate::revalidate::FileId;
+use crate::units::ByteSize;
+use anyhow::{anyhow, Context};
+use crossbeam_deque::Worker;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use itertools::Itertools;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::{env, fs, io};
+use structopt::StructOpt;
+
+// Options controlling what is scanned and how matches are found
+// Shared by every subcommand so they are marked global
+#[derive(StructOpt, Debug)]
+pub struct ScanArgs {
+    #[structopt(
+        short,
+        long,
+        global = true,
+        number_of_values = 1,
+        parse(from_os_str),
+        help = "Directory to start search. May be repeated. Default: current directory"
+    )]
+    pub directory: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        global = true,
+        parse(from_os_str),
+        help = "File listing directories to start search from, one per line. Lines starting with # are ignored. Use - for stdin"
+    )]
+    pub directories_from: Option<PathBuf>,
+
+    #[structopt(
+        short,
+        long,
+        global = true,
+        parse(from_os_str),
+        help = "Include .gitignores between root and target directory"
+    )]
+    pub root: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        global = true,
+        default_value = "0",
+        help = "Minimum size to nuke, such as 4096, 500MB or 2GiB"
+    )]
+    pub min_file_size: ByteSize,
+
+    #[structopt(long, global = true, help = "Number of threads to use. Default: num physical cores")]
+    pub num_threads: Option<usize>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Prints which glob and which .gitignore matched each path"
+    )]
+    pub print_glob_matches: bool,
+
+    #[structopt(long, global = true, help = "Include global .gitignore for matches")]
+    pub include_global_ignore: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        use_delimiter = true,
+        number_of_values = 1,
+        possible_values = PRESET_NAMES,
+        help = "Built-in patterns for common build output. Only applied next to a project marker, such as Cargo.toml for rust"
+    )]
+    pub preset: Vec<String>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Detect projects by marker files, such as Cargo.toml, and nuke their build output directories"
+    )]
+    pub projects: bool,
+
+    #[structopt(
+        long = "exclude-dir",
+        alias = "prune",
+        global = true,
+        number_of_values = 1,
+        help = "Skips directories matching this .gitignore style glob. They are neither walked nor nuked. May be repeated"
+    )]
+    pub exclude_dir: Vec<String>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Only evaluates paths up to N levels below each starting directory. Deeper directories are not walked"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Walks into directories whitelisted by .gitnuke so ignored paths inside them are still nuked"
+    )]
+    pub descend_whitelisted: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        help = "Reads every directory and ignore file from disk instead of reusing those unchanged since the last run"
+    )]
+    pub no_cache: bool,
+}
+
+// A path hidden by an ignore file, along with its total size on disk
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub path: PathBuf,
+    pub size: u64,
+
+    // Number of files, counting the path itself if it is a file
+    pub num_files: u64,
+
+    // Closest source control root containing the path
+    pub repo_root: Option<PathBuf>,
+
+    // Glob that ignored the path and where it came from. None for project outputs that no glob matched.
+    pub glob: Option<String>,
+    pub source: Option<IgnoreSource>,
+
+    // Set if the path is a build output of a detected project, see `--projects`
+    pub project: Option<Project>,
+
+    // Set if the path is or contains a source control root, such as a nested checkout
+    pub contains_vcs_root: bool,
+
+    // Identity of the path when it was scanned, see `revalidate`
+    pub id: Option<FileId>,
+}
+
+// Everything found by a scan
+pub struct Scanned {
+    pub candidates: Vec<Candidate>,
+
+    // Total size of the scanned tree, including candidates below `--min-file-size` and paths beyond `--max-depth`
+    // Whitelisted and excluded directories that weren't walked aren't included.
+    pub total_bytes: u64,
+}
+
+impl Candidate {
+    pub fn new(path: PathBuf) -> Candidate {
+        Candidate {
+            path,
+            size: 0,
+            num_files: 0,
+            repo_root: None,
+            glob: None,
+            source: None,
+            project: None,
+            contains_vcs_root: false,
+            id: None,
+        }
+    }
+}
+
+// Directory to scan, the stacks that apply to it, and its depth below the starting directory
+type ScanItem = (IgnoreStacks, PathBuf, usize);
+
+// Absolute paths of the directories to start searching from
+// Directories inside another starting directory are dropped since they are scanned anyway.
+pub fn starting_dirs(args: &ScanArgs) -> anyhow::Result<Vec<PathBuf>> {
+    // Gather dirs from the command line and the list file
+    let mut dirs = args.directory.clone();
+    if let Some(list_path) = &args.directories_from {
+        let list = if list_path.as_os_str() == "-" {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(list_path)
+        }
+        .with_context(|| format!("Failed to read directory list [{}]", list_path.display()))?;
+        dirs.extend(
+            list.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(PathBuf::from),
+        );
+    }
+
+    // Determine starting dirs
+    let cwd = env::current_dir()?;
+    if dirs.is_empty() {
+        dirs.push(cwd.clone());
+    }
+
+    // Verify starting dirs are valid
+    let mut starting_dirs: Vec<PathBuf> = Default::default();
+    for dir in dirs {
+        let dir = cwd.join(dir);
+        if !dir.exists() {
+            return Err(anyhow!("Directory [{:?}] does not exist", dir));
+        } else if !dir.is_dir() {
+            return Err(anyhow!("[{:?}] is not a directory", dir));
+        }
+        starting_dirs.push(fs::canonicalize(dir)?);
+    }
+
+    // Drop duplicates and nested dirs
+    starting_dirs.sort();
+    starting_dirs.dedup_by(|dir, prev| dir.starts_with(prev));
+    Ok(starting_dirs)
+}
+
+// Starting directory containing `path`
+pub fn starting_dir_of<'a>(starting_dirs: &'a [PathBuf], path: &Path) -> Option<&'a Path> {
+    starting_dirs
+        .iter()
+        .find(|dir| path.starts_with(dir))
+        .map(PathBuf::as_path)
+}
+
+// Ignore stacks for a starting directory, including parent ignores if `--root` is set
+pub fn starting_stacks_for(args: &ScanArgs, starting_dir: &Path, verbose: bool) -> anyhow::Result<IgnoreStacks> {
+    let root = args
+        .root
+        .as_ref()
+        .map(|root| fs::canonicalize(root).with_context(|| format!("Failed to find root [{}]", root.display())))
+        .transpose()?;
+    IgnoreStacks::new(
+        starting_dir,
+        args.include_global_ignore,
+        root.as_deref(),
+        presets::find_presets(&args.preset)?,
+        verbose,
+    )
+}
+
+// Directories skipped by `--exclude-dir`, rooted at `starting_dir`
+pub fn exclude_matcher(args: &ScanArgs, starting_dir: &Path) -> anyhow::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(starting_dir);
+    for glob in &args.exclude_dir {
+        builder.add_line(None, glob)?;
+    }
+    Ok(builder.build()?)
+}
+
+// Walks the starting directories and returns every ignored path, sorted by size
+// Errors for individual paths are recorded in `error_report`. Only fatal errors are returned.
+// Directories and ignore files unchanged since the last run are taken from `cache`.
+pub fn scan(
+    args: &ScanArgs,
+    starting_dirs: &[PathBuf],
+    cache: ScanCache,
+    error_report: &ErrorReport,
+) -> anyhow::Result<Scanned> {
+    let num_threads: usize = args.num_threads.unwrap_or_else(num_cpus::get_physical);
+
+    // Helper to record job errors
+    let record_job_errors = |errors: Vec<JobError<PathError>>| {
+        for e in errors {
+            match e {
+                JobError::Failed(e) => error_report.record(e),
+                JobError::Panicked(msg) => error_report.record(PathError::panic(msg)),
+            }
+        }
+    };
+
+    // Project outputs found outside their own project directory
+    let cargo_target_dir_env = env::var_os("CARGO_TARGET_DIR");
+    let external_outputs: Mutex<Vec<ProjectOutput>> = Default::default();
+
+    // Size of files directly inside walked directories
+    let walked_bytes = AtomicU64::new(0);
+
+    // Directories just beyond `--max-depth`, which are only sized
+    let deep_dirs: Mutex<Vec<PathBuf>> = Default::default();
+
+    // Build ignore stacks for each starting directory
+    let mut starting_stacks: Vec<IgnoreStacks> = Default::default();
+    for starting_dir in starting_dirs {
+        starting_stacks.push(starting_stacks_for(args, starting_dir, args.print_glob_matches)?);
+    }
+
+    // Directories excluded from the walk, rooted at each starting directory
+    let excludes: Vec<Gitignore> = starting_dirs
+        .iter()
+        .map(|starting_dir| exclude_matcher(args, starting_dir))
+        .collect::<anyhow::Result<_>>()?;
+    let is_excluded = |dir: &Path| {
+        starting_dirs
+            .iter()
+            .zip(&excludes)
+            .find(|(starting_dir, _)| dir.starts_with(starting_dir))
+            .is_some_and(|(_, exclude)| exclude.matched(dir, true).is_ignore())
+    };
+
+    // Recursive job takes a path, checks if it's ignored, and recurses into subdirs if needed
+    // Return value is result for the path only. Sub-directories will run separately
+    // and return their own result. Depth is 0 for starting directories.
+    let recursive_job = |(stacks, path, depth): ScanItem, worker: &Worker<_>| -> Result<Vec<Candidate>, PathError> {
+        let mut job_ignores: Vec<_> = Default::default();
+
+        // Get directory children
+        let (listing, child_errors) = cached_listing(&path, true, &cache)?;
+        for e in child_errors {
+            error_report.record(e);
+        }
+        walked_bytes.fetch_add(listing.files_size, Ordering::Relaxed);
+
+        // Children beyond `--max-depth` are never candidates, but their size still counts towards the total
+        if explain::is_too_deep(args, depth + 1) {
+            let mut deep_dirs = deep_dirs.lock().unwrap();
+            deep_dirs.extend(
+                listing
+                    .dirs
+                    .iter()
+                    .map(|name| path.join(name))
+                    .filter(|child_path| !is_excluded(child_path)),
+            );
+            return Ok(job_ignores);
+        }
+
+        let child_names: Vec<OsString> = listing
+            .dirs
+            .iter()
+            .chain(listing.files.iter().flatten())
+            .map(|name| name.as_os_str().to_owned())
+            .collect();
+        let children: Vec<(PathBuf, bool)> = listing
+            .dirs
+            .iter()
+            .map(|name| (path.join(name), true))
+            .chain(listing.files.iter().flatten().map(|name| (path.join(name), false)))
+            .collect();
+
+        // Add ignores from this directory
+        let stacks = stacks.enter_dir(&path, &child_names, &cache, args.print_glob_matches);
+
+        // Detect projects rooted in this directory
+        // Outputs that aren't children of this directory are checked against ignores once the walk is done
+        let mut project_outputs = Vec::new();
+        if args.projects {
+            for output in projects::detect(&path, &child_names, cargo_target_dir_env.as_deref()) {
+                if output.path.parent() == Some(&path) {
+                    project_outputs.push(output);
+                } else {
+                    external_outputs.lock().unwrap().push(output);
+                }
+            }
+        }
+
+        // Process each child in directory
+        for (child_path, is_dir) in children {
+            let project = project_outputs
+                .iter()
+                .find(|output| output.path == child_path)
+                .map(|output| output.project.clone());
+            let (action, matched) = explain::classify(
+                args,
+                &stacks,
+                &child_path,
+                is_dir,
+                || is_excluded(&child_path),
+                project.is_some(),
+            );
+
+            if args.print_glob_matches {
+                match (&action, &matched) {
+                    (ChildAction::Excluded, _) => println!("Excluded [{:?}]", child_path),
+                    (_, Some((layer, m))) => println!(
+                        "Glob [{:?}] from [{}] matched path [{:?}]",
+                        m.inner().unwrap().original(),
+                        layer.source,
+                        child_path
+                    ),
+                    (_, None) => {}
+                }
+            }
+
+            // Directories whitelisted by .gitignore are walked like in git. Directories whitelisted by
+            // .gitnuke are left alone unless `--descend-whitelisted` is set.
+            match action {
+                ChildAction::Nuke => {
+                    // Project outputs that no glob matched have neither glob nor source
+                    let (glob, source) = match matched {
+                        Some((layer, m)) => (
+                            m.inner().map(|glob| glob.original().to_owned()),
+                            Some(layer.source.clone()),
+                        ),
+                        None => (None, None),
+                    };
+                    job_ignores.push(Candidate {
+                        repo_root: stacks.repo_root.as_deref().map(Path::to_owned),
+                        glob,
+                        source,
+                        project,
+                        ..Candidate::new(child_path)
+                    });
+                }
+                ChildAction::Descend => worker.push((stacks.clone(), child_path, depth + 1)),
+                ChildAction::Excluded | ChildAction::Keep => {}
+            }
+        }
+
+        // Return ignored paths for path
+        Ok(job_ignores)
+    };
+
+    // Initialize data
+    let initial_data: Vec<_> = starting_stacks
+        .iter()
+        .cloned()
+        .zip(starting_dirs.iter().cloned())
+        .map(|(stacks, dir)| (stacks, dir, 0))
+        .collect();
+
+    // Run recursive jobs
+    let scan_results = job_system::run_recursive_job(initial_data, recursive_job, num_threads);
+    record_job_errors(scan_results.errors);
+    let mut ignored_paths: Vec<Candidate> = scan_results.outputs.into_iter().flatten().collect();
+
+    // Project outputs outside their project, such as a shared cargo target-dir
+    // Only outputs inside a starting directory that aren't whitelisted are nuked
+    for output in external_outputs.into_inner().unwrap() {
+        let path = match fs::canonicalize(&output.path) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        let idx = match starting_dirs.iter().position(|dir| path.starts_with(dir)) {
+            Some(idx) => idx,
+            None => continue,
+        };
+        if path.ancestors().any(&is_excluded) {
+            continue;
+        }
+        let stacks = starting_stacks[idx].clone();
+        if let Ok(verdict) = explain::evaluate(&starting_dirs[idx], stacks, &path, args, false) {
+            if matches!(verdict, Verdict::Nuke(_) | Verdict::Untouched) {
+                ignored_paths.push(Candidate {
+                    repo_root: path
+                        .ancestors()
+                        .skip(1)
+                        .find(|dir| is_vcs_root(dir))
+                        .map(Path::to_owned),
+                    project: Some(output.project),
+                    ..Candidate::new(path)
+                });
+            }
+        }
+    }
+
+    // Drop duplicates and paths nested inside another candidate
+    // Sorting by path puts every path immediately after its ancestors
+    ignored_paths.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut deduped: Vec<Candidate> = Vec::with_capacity(ignored_paths.len());
+    for candidate in ignored_paths {
+        match deduped.last_mut() {
+            Some(last) if candidate.path.starts_with(&last.path) => {
+                if last.project.is_none() {
+                    last.project = candidate.project;
+                }
+            }
+            _ => deduped.push(candidate),
+        }
+    }
+
+    // Second recursive job to compute size of ignored directories, and of directories beyond `--max-depth`
+    // Also returns whether the path is a source control root, which is always checked on disk
+    let num_candidates = deduped.len();
+    let file_candidate_bytes = AtomicU64::new(0);
+    let recursive_dir_size_job =
+        |(root_idx, path): (usize, PathBuf), worker: &Worker<_>| -> Result<(usize, u64, u64, bool), PathError> {
+            // Get type of path
+            let path_meta = fs::metadata(&path).map_err(|e| PathError::io(Operation::Metadata, &path, e))?;
+
+            // If file, return result immediately
+            // Only candidates themselves can be files, and their size was already counted by the walk
+            if path_meta.is_file() {
+                if root_idx < num_candidates {
+                    file_candidate_bytes.fetch_add(path_meta.len(), Ordering::Relaxed);
+                }
+                return Ok((root_idx, path_meta.len(), 1, false));
+            }
+
+            // Get directory listing
+            let (listing, child_errors) = cached_listing_with_meta(&path, &path_meta, false, &cache)?;
+            for e in child_errors {
+                error_report.record(e);
+            }
+
+            // Add directories to the worker
+            for name in &listing.dirs {
+                worker.push((root_idx, path.join(name)));
+            }
+
+            Ok((root_idx, listing.files_size, listing.num_files, is_vcs_root(&path)))
+        };
+
+    // Compute path sizes
+    // Directories beyond `--max-depth` follow the candidates so their sizes can be told apart
+    let ignored_paths: Vec<(usize, PathBuf)> = deduped
+        .iter()
+        .map(|candidate| candidate.path.clone())
+        .chain(deep_dirs.into_inner().unwrap())
+        .enumerate()
+        .collect();
+    let dir_sizes = job_system::run_recursive_job(ignored_paths, recursive_dir_size_job, num_threads);
+    record_job_errors(dir_sizes.errors);
+
+    // Sum sizes and file counts
+    let mut ignore_path_sizes: Vec<(u64, u64, bool)> = vec![(0, 0, false); num_candidates];
+    let mut deep_bytes = 0;
+    for (idx, size, num_files, vcs_root) in dir_sizes.outputs {
+        if idx >= num_candidates {
+            deep_bytes += size;
+            continue;
+        }
+        ignore_path_sizes[idx].0 += size;
+        ignore_path_sizes[idx].1 += num_files;
+        ignore_path_sizes[idx].2 |= vcs_root;
+    }
+
+    // Walked directories count the files directly inside them, so only candidate and deep directories are added
+    let candidate_bytes: u64 = ignore_path_sizes.iter().map(|(size, _, _)| size).sum();
+    let total_bytes = walked_bytes.into_inner() + candidate_bytes + deep_bytes - file_candidate_bytes.into_inner();
+
+    // Sort ignored paths by size
+    let candidates = deduped
+        .into_iter()
+        .zip(ignore_path_sizes)
+        .map(|(candidate, (size, num_files, contains_vcs_root))| Candidate {
+            size,
+            num_files,
+            contains_vcs_root,
+            id: FileId::of(&candidate.path).ok(),
+            ..candidate
+        })
+        .filter(|c| c.size >= args.min_file_size.0)
+        .sorted_by_key(|c| c.size)
+        .collect();
+
+    // A stale cache only costs speed, so failing to save it is not fatal
+    if let Err(e) = cache.save(starting_dirs) {
+        eprintln!("Warning: {:#}", e);
+    }
+
+    Ok(Scanned {
+        candidates,
+        total_bytes,
+    })
+}
+
+// Contents of a single directory
+struct DirListing {
+    // Names of subdirectories
+    dirs: Vec<PathBuf>,
+
+    // Names of files. None if only the total size was recorded.
+    files: Option<Vec<PathBuf>>,
+
+    // Number and total size of the files directly in this directory
+    num_files: u64,
+    files_size: u64,
+}
+
+// Directory listing, taken from `cache` if the directory is unchanged since the last run
+fn cached_listing(dir: &Path, with_files: bool, cache: &ScanCache) -> Result<(DirListing, Vec<PathError>), PathError> {
+    if !cache.is_enabled() {
+        return read_listing(dir, with_files);
+    }
+    let meta = fs::metadata(dir).map_err(|e| PathError::io(Operation::Metadata, dir, e))?;
+    cached_listing_with_meta(dir, &meta, with_files, cache)
+}
+
+// Same as `cached_listing`, where `meta` is the metadata of `dir` fetched by the caller
+// The metadata must be fetched before the listing is read, so a change made while reading it is noticed
+// on the next run.
+fn cached_listing_with_meta(
+    dir: &Path,
+    meta: &fs::Metadata,
+    with_files: bool,
+    cache: &ScanCache,
+) -> Result<(DirListing, Vec<PathError>), PathError> {
+    if let Some(record) = cache.get_dir(dir, meta, with_files) {
+        let listing = DirListing {
+            dirs: record.dirs.into_iter().map(PathBuf::from).collect(),
+            files: record.files.map(|files| files.into_iter().map(PathBuf::from).collect()),
+            num_files: record.num_files,
+            files_size: record.files_size,
+        };
+        return Ok((listing, Vec::new()));
+    }
+
+    // Only listings read without errors, and whose names are all valid UTF-8, are cached
+    let (listing, errors) = read_listing(dir, with_files)?;
+    if cache.is_enabled() && errors.is_empty() {
+        let names = |names: &[PathBuf]| -> Option<Vec<String>> {
+            names.iter().map(|name| name.to_str().map(str::to_owned)).collect()
+        };
+        let files = match &listing.files {
+            Some(files) => names(files).map(Some),
+            None => Some(None),
+        };
+        if let (Some(dirs), Some(files)) = (names(&listing.dirs), files) {
+            cache.insert_dir(dir, meta, dirs, files, listing.num_files, listing.files_size);
+        }
+    }
+    Ok((listing, errors))
+}
+
+// Read a directory listing from disk
+// Errors for individual children are returned alongside the listing.
+fn read_listing(dir: &Path, with_files: bool) -> Result<(DirListing, Vec<PathError>), PathError> {
+    let mut listing = DirListing {
+        dirs: Vec::new(),
+        files: if with_files { Some(Vec::new()) } else { None },
+        num_files: 0,
+        files_size: 0,
+    };
+    let mut errors = Vec::new();
+
+    let read_dir = fs::read_dir(dir).map_err(|e| PathError::io(Operation::ReadDir, dir, e))?;
+    for child in read_dir {
+        let result = || -> Result<(), PathError> {
+            let child_path = child.map_err(|e| PathError::io(Operation::ReadDir, dir, e))?.path();
+            let child_meta =
+                fs::metadata(&child_path).map_err(|e| PathError::io(Operation::Metadata, &child_path, e))?;
+
+            let name = PathBuf::from(child_path.file_name().unwrap_or_default());
+            if child_meta.is_dir() {
+                listing.dirs.push(name);
+            } else {
+                listing.num_files += 1;
+                listing.files_size += child_meta.len();
+                if let Some(files) = &mut listing.files {
+                    files.push(name);
+                }
+            }
+            Ok(())
+        }();
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    Ok((listing, errors))
+}
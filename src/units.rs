@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use std::str::FromStr;
+
+// How sizes are printed, see `--units`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Units {
+    // Powers of 1000: KB, MB, GB
+    Si,
+
+    // Powers of 1024: KiB, MiB, GiB
+    Iec,
+
+    // Exact byte counts
+    Bytes,
+}
+
+pub const UNITS_NAMES: &[&str] = &["si", "iec", "bytes"];
+
+const SI_SUFFIXES: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB", "EB"];
+const IEC_SUFFIXES: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+impl FromStr for Units {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Units> {
+        match s {
+            "si" => Ok(Units::Si),
+            "iec" => Ok(Units::Iec),
+            "bytes" => Ok(Units::Bytes),
+            _ => Err(anyhow!("Unknown units [{}]", s)),
+        }
+    }
+}
+
+impl Units {
+    // Format a byte count with one decimal, such as `1.5 GB` or `1.4 GiB`
+    pub fn format(self, bytes: u64) -> String {
+        let (base, suffixes) = match self {
+            Units::Si => (1000.0, SI_SUFFIXES),
+            Units::Iec => (1024.0, IEC_SUFFIXES),
+            Units::Bytes => return format!("{} B", bytes),
+        };
+
+        if (bytes as f64) < base {
+            return format!("{} B", bytes);
+        }
+
+        // Move to the next suffix when rounding would print a full unit, such as `1000.0 KB`
+        let mut value = bytes as f64;
+        let mut order = 0;
+        while order + 1 < suffixes.len() && (value * 10.0).round() / 10.0 >= base {
+            value /= base;
+            order += 1;
+        }
+        format!("{:.1} {}", value, suffixes[order])
+    }
+
+    // Format a signed change in bytes, such as `+1.5 GB`
+    pub fn format_delta(self, delta: i128) -> String {
+        let sign = if delta < 0 { "-" } else { "+" };
+        format!(
+            "{}{}",
+            sign,
+            self.format(delta.unsigned_abs().min(u64::MAX as u128) as u64)
+        )
+    }
+}
+
+// Byte count parsed from a human-friendly size such as `500MiB`, `1.5 GB` or `4096`
+// SI suffixes are powers of 1000 and IEC suffixes are powers of 1024. Suffixes are case insensitive.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ByteSize> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let (number, suffix) = s.split_at(split);
+
+        let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000u64.pow(2),
+            "g" | "gb" => 1000u64.pow(3),
+            "t" | "tb" => 1000u64.pow(4),
+            "p" | "pb" => 1000u64.pow(5),
+            "ki" | "kib" => 1 << 10,
+            "mi" | "mib" => 1 << 20,
+            "gi" | "gib" => 1 << 30,
+            "ti" | "tib" => 1 << 40,
+            "pi" | "pib" => 1 << 50,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown size suffix in [{}]. Expected a size such as 500MB or 2GiB",
+                    s
+                ))
+            }
+        };
+
+        // Whole numbers are exact, fractions are rounded to the nearest byte
+        let bytes = if let Ok(whole) = number.parse::<u64>() {
+            whole.checked_mul(multiplier)
+        } else {
+            number
+                .parse::<f64>()
+                .ok()
+                .map(|value| (value * multiplier as f64).round())
+                .filter(|bytes| bytes.is_finite() && *bytes < u64::MAX as f64)
+                .map(|bytes| bytes as u64)
+        };
+        bytes
+            .map(ByteSize)
+            .ok_or_else(|| anyhow!("Invalid size [{}]. Expected a size such as 500MB or 2GiB", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_si() {
+        assert_eq!(Units::Si.format(0), "0 B");
+        assert_eq!(Units::Si.format(999), "999 B");
+        assert_eq!(Units::Si.format(1999), "2.0 KB");
+        assert_eq!(Units::Si.format(1449), "1.4 KB");
+        assert_eq!(Units::Si.format(999_960), "1.0 MB");
+        assert_eq!(Units::Si.format(12_345_678_901), "12.3 GB");
+        assert_eq!(Units::Si.format(u64::MAX), "18.4 EB");
+    }
+
+    #[test]
+    fn format_iec_and_bytes() {
+        assert_eq!(Units::Iec.format(1023), "1023 B");
+        assert_eq!(Units::Iec.format(1536), "1.5 KiB");
+        assert_eq!(Units::Iec.format(5 << 30), "5.0 GiB");
+        assert_eq!(Units::Bytes.format(1999), "1999 B");
+        assert_eq!(Units::Si.format_delta(-1500), "-1.5 KB");
+        assert_eq!(Units::Si.format_delta(0), "+0 B");
+    }
+
+    #[test]
+    fn parse_sizes() {
+        let parse = |s: &str| s.parse::<ByteSize>().map(|b| b.0).ok();
+        assert_eq!(parse("4096"), Some(4096));
+        assert_eq!(parse("500MiB"), Some(500 << 20));
+        assert_eq!(parse("500 MB"), Some(500_000_000));
+        assert_eq!(parse("1.5gb"), Some(1_500_000_000));
+        assert_eq!(parse("2k"), Some(2000));
+        assert_eq!(parse("0.5KiB"), Some(512));
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("12 parsecs"), None);
+        assert_eq!(parse("-5MB"), None);
+        assert_eq!(parse("99999999999PB"), None);
+    }
+}
@@ -0,0 +1,90 @@
+// Runs the check subcommand against fixture trees
+mod common;
+
+use common::{Dir, File, Fixture, Sized};
+
+fn fixture() -> Fixture {
+    Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n*.log\n"),
+        Sized("target/debug/app", 1500),
+        Sized("build.log", 20),
+    ])
+}
+
+#[test]
+fn report_on_stdout_is_parseable() {
+    let fixture = fixture();
+    let output = fixture
+        .command(&["check", "--max-candidate-bytes", "1000", "--report", "-"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5));
+
+    // Human readable output goes to stderr instead
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], false);
+    assert_eq!(report["total_bytes"], 1520);
+    assert_eq!(report["offenders"][0]["bytes"], 1500);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("scanning for targets"));
+    assert!(stderr.contains("❌"));
+
+    let output = fixture
+        .command(&[
+            "check",
+            "--max-total-bytes",
+            "2000",
+            "--report",
+            "-",
+            "--report-format",
+            "junit",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("<?xml"));
+}
+
+#[test]
+fn summary_on_stdout_without_report() {
+    let output = fixture()
+        .command(&["check", "--max-total-bytes", "2000"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("✅"));
+}
+
+#[test]
+fn glob_matches_never_mix_with_report_on_stdout() {
+    let output = fixture()
+        .command(&[
+            "check",
+            "--max-total-bytes",
+            "2000",
+            "--report",
+            "-",
+            "--print-glob-matches",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+#[cfg(unix)]
+fn scan_errors_fail_the_check() {
+    let fixture = fixture();
+    std::os::unix::fs::symlink(fixture.path("missing"), fixture.path("target/dangling")).unwrap();
+    let output = fixture
+        .command(&["check", "--max-total-bytes", "2000", "--report", "-"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], false);
+    assert_eq!(report["num_scan_errors"], 1);
+}
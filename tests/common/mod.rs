@@ -0,0 +1,166 @@
+// Fixture trees for integration tests
+// A fixture is declared as a list of entries, written to a temporary directory, and scanned by running
+// the real binary. Each test file only uses some of these helpers.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+// Single entry of a fixture tree. Paths are relative to the fixture root and use `/`.
+pub enum Entry<'a> {
+    // Empty directory, such as `.git`
+    Dir(&'a str),
+
+    // File with text content, such as a `.gitignore`
+    File(&'a str, &'a str),
+
+    // File of the given size in bytes
+    Sized(&'a str, u64),
+}
+
+pub use Entry::{Dir, File, Sized};
+
+// Result of a dry run
+pub struct Scan {
+    pub code: Option<i32>,
+    pub stdout: String,
+
+    // Candidate paths, relative to the fixture root, and their sizes
+    pub candidates: BTreeMap<String, u64>,
+}
+
+pub struct Fixture {
+    tempdir: TempDir,
+
+    // Canonical root of the tree. Everything else in `tempdir` is scratch space.
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new(entries: &[Entry]) -> Fixture {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path().join("tree");
+        fs::create_dir(&root).unwrap();
+        let root = root.canonicalize().unwrap();
+
+        let fixture = Fixture { tempdir, root };
+        for entry in entries {
+            match *entry {
+                Dir(path) => fs::create_dir_all(fixture.path(path)).unwrap(),
+                File(path, content) => fixture.write(path, content.as_bytes()),
+                Sized(path, size) => fixture.write(path, &vec![b'x'; size as usize]),
+            }
+        }
+        fixture
+    }
+
+    fn write(&self, path: &str, content: &[u8]) {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    // Absolute path of an entry
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    pub fn exists(&self, relative: &str) -> bool {
+        self.path(relative).exists()
+    }
+
+    // Home directory used by the binary, so user config, global ignores and caches never leak in
+    pub fn home(&self) -> PathBuf {
+        self.tempdir.path().join("home")
+    }
+
+    // Directory holding the audit log, on every platform
+    pub fn state_dir(&self) -> PathBuf {
+        self.home().join("state")
+    }
+
+    // Command running the binary from the fixture root
+    pub fn command(&self, args: &[&str]) -> Command {
+        let home = self.home();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_fts_gitignore_nuke"));
+        command
+            .current_dir(&self.root)
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_CACHE_HOME", home.join(".cache"))
+            .env("XDG_STATE_HOME", home.join(".local").join("state"))
+            .env("FTS_GITIGNORE_NUKE_STATE_DIR", self.state_dir())
+            .env("FTS_GITIGNORE_NUKE_CACHE_DIR", home.join("cache"))
+            .args(args);
+        command
+    }
+
+    // Dry run and collect candidates from the saved report
+    pub fn scan(&self, args: &[&str]) -> Scan {
+        let report_path = self.tempdir.path().join("report.json");
+        let _ = fs::remove_file(&report_path);
+        let output = self
+            .command(args)
+            .arg("--dry-run")
+            .arg("--save-report")
+            .arg(&report_path)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        assert!(
+            report_path.exists(),
+            "No report saved\nstdout: {}\nstderr: {}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let report: serde_json::Value = serde_json::from_slice(&fs::read(&report_path).unwrap()).unwrap();
+        let candidates = report["candidates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|candidate| {
+                let path = Path::new(candidate["path"].as_str().unwrap());
+                (self.relative(path), candidate["bytes"].as_u64().unwrap())
+            })
+            .collect();
+
+        Scan {
+            code: output.status.code(),
+            stdout,
+            candidates,
+        }
+    }
+
+    // Run and answer the prompt with `input`, such as "NUKE\n"
+    pub fn run_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = self
+            .command(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    // Path relative to the fixture root, with `/` separators
+    pub fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or_else(|_| panic!("[{:?}] is outside the fixture", path))
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+}
+
+impl Scan {
+    // Candidate paths, without sizes
+    pub fn paths(&self) -> Vec<&str> {
+        self.candidates.keys().map(String::as_str).collect()
+    }
+}
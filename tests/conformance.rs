@@ -0,0 +1,181 @@
+// Compares what gets nuked against what git itself reports as ignored
+//
+// Each case in tests/conformance is a small tree followed by a snapshot of git's answer:
+//
+//   # Description
+//   start: sub/dir          optional, scan from here with --root at the repo
+//   --- path/to/file        every line until the next `---` is the file's content
+//   === git                 every following line is a file git reports as ignored
+//
+// Snapshots come from `git status --ignored --porcelain -uall` and are regenerated with
+// `cargo test --test conformance -- --ignored`.
+
+mod common;
+
+use common::{Dir, File, Fixture};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SNAPSHOT_HEADER: &str = "=== git";
+
+struct Case {
+    name: String,
+    path: PathBuf,
+    start: Option<String>,
+    files: Vec<(String, String)>,
+    expected: BTreeSet<String>,
+}
+
+fn cases() -> Vec<Case> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    paths.sort();
+    paths.into_iter().map(|path| parse_case(&path)).collect()
+}
+
+fn parse_case(path: &Path) -> Case {
+    let mut case = Case {
+        name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+        path: path.to_owned(),
+        start: None,
+        files: Vec::new(),
+        expected: BTreeSet::new(),
+    };
+
+    let text = fs::read_to_string(path).unwrap();
+    let mut in_snapshot = false;
+    for line in text.lines() {
+        if in_snapshot {
+            case.expected.insert(line.to_owned());
+        } else if line == SNAPSHOT_HEADER {
+            in_snapshot = true;
+        } else if let Some(file) = line.strip_prefix("--- ") {
+            case.files.push((file.to_owned(), String::new()));
+        } else if let Some((_, content)) = case.files.last_mut() {
+            content.push_str(line);
+            content.push('\n');
+        } else if let Some(start) = line.strip_prefix("start: ") {
+            case.start = Some(start.to_owned());
+        } else {
+            assert!(line.starts_with('#'), "Unexpected line [{}] in [{}]", line, case.name);
+        }
+    }
+    case
+}
+
+// Write the case's files below `root`
+fn materialize(case: &Case, root: &Path) {
+    for (file, content) in &case.files {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+// Every file below `path`, relative to the fixture root
+fn files_below(fixture: &Fixture, path: &Path, files: &mut BTreeSet<String>) {
+    if path.is_dir() {
+        for entry in fs::read_dir(path).unwrap() {
+            files_below(fixture, &entry.unwrap().path(), files);
+        }
+    } else {
+        files.insert(fixture.relative(path));
+    }
+}
+
+// Files that would be nuked, relative to the repo root
+fn nuked_files(case: &Case) -> BTreeSet<String> {
+    let mut entries = vec![Dir(".git")];
+    entries.extend(case.files.iter().map(|(file, content)| File(file, content)));
+    let fixture = Fixture::new(&entries);
+
+    let start = case.start.as_deref().unwrap_or(".");
+    let scan = fixture.scan(&["--directory", start, "--root", "."]);
+    assert!(
+        scan.code == Some(0) || scan.code == Some(1),
+        "[{}] failed: {}",
+        case.name,
+        scan.stdout
+    );
+
+    let mut files = BTreeSet::new();
+    for path in scan.candidates.keys() {
+        files_below(&fixture, &fixture.path(path), &mut files);
+    }
+    files
+}
+
+#[test]
+fn matches_git() {
+    let cases = cases();
+    assert!(!cases.is_empty());
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let nuked = nuked_files(case);
+        if nuked != case.expected {
+            failures.push(format!(
+                "[{}]\n  git ignores: {:?}\n  nuked:       {:?}",
+                case.name, case.expected, nuked
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Rewrite every snapshot from the installed git
+#[test]
+#[ignore]
+fn regenerate_git_snapshots() {
+    for case in cases() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let root = tempdir.path();
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(root)
+                .args(["-c", "core.excludesFile="])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            output.stdout
+        };
+
+        // Init first, so the case can overwrite files inside `.git`
+        git(&["init", "-q"]);
+        materialize(&case, root);
+
+        let status = git(&["status", "--ignored", "--porcelain", "-uall", "-z"]);
+        let prefix = case
+            .start
+            .as_ref()
+            .map(|start| format!("{}/", start))
+            .unwrap_or_default();
+        let ignored: BTreeSet<String> = String::from_utf8(status)
+            .unwrap()
+            .split('\0')
+            .filter_map(|entry| entry.strip_prefix("!! "))
+            .filter(|file| file.starts_with(&prefix))
+            .map(str::to_owned)
+            .collect();
+
+        let text = fs::read_to_string(&case.path).unwrap();
+        let mut text = match text.find(SNAPSHOT_HEADER) {
+            Some(idx) => text[..idx].to_owned(),
+            None => text,
+        };
+        text.push_str(SNAPSHOT_HEADER);
+        text.push('\n');
+        for file in ignored {
+            text.push_str(&file);
+            text.push('\n');
+        }
+        fs::write(&case.path, text).unwrap();
+    }
+}
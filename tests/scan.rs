@@ -0,0 +1,617 @@
+// Scans and nukes fixture trees with the real binary
+mod common;
+
+use common::{Dir, File, Fixture, Sized};
+
+#[test]
+fn candidates_and_sizes() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n*.log\n"),
+        Sized("target/debug/app", 1000),
+        Sized("target/debug/deps/lib.rlib", 500),
+        Sized("build.log", 20),
+        Sized("src/main.rs", 10),
+        Sized("src/trace.log", 7),
+    ]);
+
+    let scan = fixture.scan(&[]);
+    assert_eq!(scan.code, Some(1));
+    assert_eq!(
+        scan.candidates.into_iter().collect::<Vec<_>>(),
+        vec![
+            ("build.log".to_owned(), 20),
+            ("src/trace.log".to_owned(), 7),
+            ("target".to_owned(), 1500),
+        ]
+    );
+}
+
+#[test]
+fn nothing_to_nuke() {
+    let fixture = Fixture::new(&[Dir(".git"), File(".gitignore", "*.log\n"), Sized("main.rs", 10)]);
+    let scan = fixture.scan(&[]);
+    assert_eq!(scan.code, Some(0));
+    assert!(scan.candidates.is_empty());
+    assert!(scan.stdout.contains("No ignore paths to delete."));
+}
+
+#[test]
+fn vcs_root_resets_ignores() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.o\n"),
+        Sized("a.o", 1),
+        Sized("plain/b.o", 1),
+        Dir("nested/.git"),
+        Sized("nested/c.o", 1),
+        File("nested/.gitignore", "*.tmp\n"),
+        Sized("nested/d.tmp", 1),
+        Dir("hg/.hg"),
+        Sized("hg/e.o", 1),
+    ]);
+
+    // Patterns from outside a repo don't apply inside it
+    assert_eq!(fixture.scan(&[]).paths(), vec!["a.o", "nested/d.tmp", "plain/b.o"]);
+}
+
+#[test]
+fn root_includes_parent_ignores() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.o\n"),
+        File("sub/.gitignore", "*.tmp\n"),
+        Sized("sub/a.o", 1),
+        Sized("sub/b.tmp", 1),
+        Sized("sub/deeper/c.o", 1),
+    ]);
+
+    // Parent ignores are only consulted with --root
+    assert_eq!(fixture.scan(&["-d", "sub"]).paths(), vec!["sub/b.tmp"]);
+    assert_eq!(
+        fixture.scan(&["-d", "sub", "--root", "."]).paths(),
+        vec!["sub/a.o", "sub/b.tmp", "sub/deeper/c.o"]
+    );
+}
+
+#[test]
+fn root_stops_at_vcs_root() {
+    let fixture = Fixture::new(&[
+        File(".gitignore", "*.o\n"),
+        Dir("repo/.git"),
+        File("repo/.gitignore", "*.tmp\n"),
+        Sized("repo/sub/a.o", 1),
+        Sized("repo/sub/b.tmp", 1),
+    ]);
+
+    assert_eq!(
+        fixture.scan(&["-d", "repo/sub", "--root", "."]).paths(),
+        vec!["repo/sub/b.tmp"]
+    );
+}
+
+#[test]
+fn gitnuke_takes_precedence() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.key\nLocalContent/\n"),
+        File(".gitnuke", "!secret.key\n!/LocalContent\n"),
+        Sized("secret.key", 1),
+        Sized("other.key", 1),
+        Sized("LocalContent/texture.png", 1),
+        // Every .gitnuke beats every .gitignore, however deep
+        File("sub/.gitignore", "secret.key\n"),
+        Sized("sub/secret.key", 1),
+    ]);
+
+    assert_eq!(fixture.scan(&[]).paths(), vec!["other.key"]);
+}
+
+#[test]
+fn global_ignore_is_opt_in() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        Sized("a.swp", 1),
+        Dir("nested/.git"),
+        Sized("nested/b.swp", 1),
+    ]);
+    let global = fixture.home().join(".config").join("git").join("ignore");
+    std::fs::create_dir_all(global.parent().unwrap()).unwrap();
+    std::fs::write(global, "*.swp\n").unwrap();
+
+    assert!(fixture.scan(&[]).candidates.is_empty());
+
+    // Global patterns survive the reset at each repo root
+    assert_eq!(
+        fixture.scan(&["--include-global-ignore"]).paths(),
+        vec!["a.swp", "nested/b.swp"]
+    );
+}
+
+#[test]
+fn builtin_whitelist() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", ".*\n"),
+        File(".gitnuke", ""),
+        Dir(".hg"),
+        Sized(".cache/data", 1),
+        Sized(".env", 1),
+    ]);
+
+    assert_eq!(fixture.scan(&[]).paths(), vec![".cache", ".env"]);
+}
+
+#[test]
+fn min_file_size() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.bin\nout/\n"),
+        Sized("small.bin", 999),
+        Sized("large.bin", 1000),
+        Sized("out/a", 600),
+        Sized("out/b", 600),
+    ]);
+
+    // Directories are filtered by their total size
+    assert_eq!(
+        fixture.scan(&["--min-file-size", "1KB"]).paths(),
+        vec!["large.bin", "out"]
+    );
+    assert_eq!(fixture.scan(&["--min-file-size", "1KiB"]).paths(), vec!["out"]);
+}
+
+#[test]
+fn nuke_deletes_only_candidates() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n*.log\n"),
+        Sized("target/debug/app", 10),
+        Sized("build.log", 10),
+        Sized("src/main.rs", 10),
+    ]);
+
+    let output = fixture.run_with_input(&[], "nope\nNUKE\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!fixture.exists("target"));
+    assert!(!fixture.exists("build.log"));
+    assert!(fixture.exists("src/main.rs"));
+    assert!(fixture.exists(".gitignore"));
+    assert!(fixture.exists(".git"));
+}
+
+#[test]
+fn quit_keeps_everything() {
+    let fixture = Fixture::new(&[Dir(".git"), File(".gitignore", "*.log\n"), Sized("build.log", 10)]);
+
+    let output = fixture.run_with_input(&[], "QUIT\n");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(fixture.exists("build.log"));
+
+    // End of input is treated as quitting
+    let output = fixture.run_with_input(&[], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(fixture.exists("build.log"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn in_use_candidates_are_not_nuked() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n*.log\n"),
+        Sized("target/debug/app", 10),
+        Sized("build.log", 10),
+    ]);
+
+    let mut sleeper = std::process::Command::new("sleep")
+        .arg("30")
+        .current_dir(fixture.path("target/debug"))
+        .spawn()
+        .unwrap();
+    let output = fixture.run_with_input(&[], "NUKE\n");
+    sleeper.kill().unwrap();
+    sleeper.wait().unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("In use by running processes"), "{}", stdout);
+    assert!(stdout.contains(&format!("pid {} (sleep) working directory", sleeper.id())));
+    assert!(fixture.exists("target/debug/app"));
+    assert!(!fixture.exists("build.log"));
+}
+
+#[test]
+fn busy_candidates_are_not_nuked() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "Intermediate/\ntarget/\n*.log\n"),
+        File("game/.gitnuke", "# lock: .build-running\n"),
+        File("game/.build-running", ""),
+        Sized("game/Intermediate/a.obj", 10),
+        Sized("target/debug/app", 10),
+        Sized("build.log", 10),
+    ]);
+
+    // Everything was just written, so it's all busy with --busy-within
+    let output = fixture.run_with_input(&["--busy-within", "10"], "NUKE\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Busy, not nuked:"), "{}", stdout);
+    assert!(stdout.contains("No ignore paths to delete."));
+    assert!(fixture.exists("build.log"));
+
+    // Without it only the declared lock applies, to everything below its .gitnuke
+    fixture.run_with_input(&[], "NUKE\n");
+    assert!(fixture.exists("game/Intermediate/a.obj"));
+    assert!(!fixture.exists("target"));
+    assert!(!fixture.exists("build.log"));
+}
+
+#[test]
+fn safety_rails() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "vendor/\n*.log\n"),
+        Dir("vendor/lib/.git"),
+        Sized("vendor/lib/src.c", 10),
+        Sized("build.log", 10),
+        Sized("a.swp", 10),
+        Sized("main.c", 10),
+    ]);
+    let global = fixture.home().join(".config").join("git").join("ignore");
+    std::fs::create_dir_all(global.parent().unwrap()).unwrap();
+    std::fs::write(global, "*.swp\n").unwrap();
+
+    // Nested checkouts are never nuked, global matches only with --allow-global
+    let output = fixture.run_with_input(&["--include-global-ignore"], "NUKE\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("contains a source control root, never nuked"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("nuked only with --allow-global"), "{}", stdout);
+    assert!(fixture.exists("vendor/lib/src.c"));
+    assert!(fixture.exists("a.swp"));
+    assert!(!fixture.exists("build.log"));
+
+    fixture.run_with_input(&["--include-global-ignore", "--allow-global"], "NUKE\n");
+    assert!(fixture.exists("vendor/lib/src.c"));
+    assert!(!fixture.exists("a.swp"));
+}
+
+#[test]
+fn large_candidates_need_a_flag() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n"),
+        Sized("target/debug/app", 1000),
+        Sized("main.rs", 10),
+    ]);
+
+    // Too small to be held back by default
+    let output = fixture.run_with_input(&["--dry-run"], "");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("--allow-large"));
+
+    let output = fixture.run_with_input(&["--large-min-size", "0"], "NUKE\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("of the scanned tree, nuked only with --allow-large"),
+        "{}",
+        stdout
+    );
+    assert!(fixture.exists("target/debug/app"));
+
+    fixture.run_with_input(&["--large-min-size", "0", "--allow-large"], "NUKE\n");
+    assert!(!fixture.exists("target"));
+}
+
+#[test]
+fn nukes_are_logged() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n*.log\n"),
+        Sized("target/debug/app", 100),
+        Sized("build.log", 20),
+        Sized("main.rs", 1000),
+    ]);
+
+    let history = || {
+        let output = fixture.command(&["history", "--paths"]).output().unwrap();
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert!(history().contains("Nothing has been nuked yet."));
+
+    fixture.run_with_input(&[], "NUKE\n");
+    let log_path = fixture.state_dir().join("audit.jsonl");
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let entries: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["path"], fixture.path("target").to_str().unwrap());
+    assert_eq!(entries[0]["bytes"], 100);
+    assert_eq!(entries[0]["glob"], "target/");
+    assert_eq!(entries[0]["source"], fixture.path(".gitignore").to_str().unwrap());
+    assert_eq!(entries[0]["error"], serde_json::Value::Null);
+
+    let history = history();
+    assert!(history.contains("Total reclaimed by 1 runs: 120 B"), "{}", history);
+    assert!(
+        history.contains(&format!("Log: [{}]", log_path.display())),
+        "{}",
+        history
+    );
+    assert!(
+        history.contains(&format!("{:?}", fixture.path("build.log"))),
+        "{}",
+        history
+    );
+}
+
+#[test]
+fn changes_after_listing_are_not_nuked() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\nout/\n*.log\n"),
+        Sized("target/debug/app", 10),
+        Sized("out/a", 10),
+        Sized("build.log", 10),
+        Sized("precious/data", 10),
+        Sized("main.rs", 100),
+    ]);
+
+    // Wait for the prompt, change the tree, then confirm
+    let mut child = fixture
+        .command(&[])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    while !line.starts_with("Type NUKE") {
+        line.clear();
+        assert!(stdout.read_line(&mut line).unwrap() > 0, "No prompt");
+    }
+
+    std::fs::write(fixture.path(".gitnuke"), "!/target\n").unwrap();
+    std::fs::remove_dir_all(fixture.path("out")).unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(fixture.path("precious"), fixture.path("out")).unwrap();
+
+    child.stdin.take().unwrap().write_all(b"NUKE\n").unwrap();
+    let mut rest = String::new();
+    std::io::Read::read_to_string(&mut stdout, &mut rest).unwrap();
+    child.wait().unwrap();
+
+    assert!(rest.contains("now whitelisted"), "{}", rest);
+    assert!(fixture.exists("target/debug/app"));
+    assert!(fixture.exists("precious/data"));
+    assert!(!fixture.exists("build.log"));
+}
+
+#[test]
+fn cached_scans() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "target/\n"),
+        Sized("target/debug/app", 100),
+        Dir("target/vendor"),
+        Sized("src/main.rs", 10),
+    ]);
+    let backdate = |paths: &[&str]| {
+        let old = filetime::FileTime::from_unix_time(1_000_000_000, 0);
+        for path in paths {
+            filetime::set_file_mtime(fixture.path(path), old).unwrap();
+        }
+    };
+    let everything = ["", ".gitignore", "target", "target/debug", "target/vendor", "src"];
+    backdate(&everything);
+    assert_eq!(fixture.scan(&[]).candidates["target"], 100);
+    assert!(fixture.home().join("cache/scan_cache.json").exists());
+
+    // Changes anywhere inside a candidate are noticed, however deep
+    std::fs::write(fixture.path("target/debug/new"), vec![b'x'; 10]).unwrap();
+    assert_eq!(fixture.scan(&[]).candidates["target"], 110);
+
+    // A source control root is always looked for on disk, even inside an unchanged directory
+    std::fs::create_dir(fixture.path("target/vendor/.git")).unwrap();
+    backdate(&everything);
+    let scan = fixture.scan(&[]);
+    assert!(
+        scan.stdout.contains("contains a source control root"),
+        "{}",
+        scan.stdout
+    );
+    std::fs::remove_dir(fixture.path("target/vendor/.git")).unwrap();
+
+    // Edited ignore files are noticed
+    std::fs::write(fixture.path(".gitignore"), "src/\n").unwrap();
+    assert_eq!(fixture.scan(&[]).paths(), vec!["src"]);
+    std::fs::write(fixture.path(".gitignore"), "target/\n").unwrap();
+
+    // Files modified in place leave their directory alone, which only --no-cache notices
+    backdate(&everything);
+    assert_eq!(fixture.scan(&[]).candidates["target"], 110);
+    std::fs::write(fixture.path("target/debug/app"), vec![b'x'; 300]).unwrap();
+    assert_eq!(fixture.scan(&["--no-cache"]).candidates["target"], 310);
+}
+
+#[test]
+fn held_back_candidates_do_not_take_top_slots() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.log\n"),
+        File("game/.gitnuke", "# lock: .building\n"),
+        File("game/.building", ""),
+        Sized("game/out.log", 1000),
+        Sized("small.log", 10),
+    ]);
+
+    // The largest candidate is busy, so the next one is offered instead
+    let scan = fixture.scan(&["--top", "1"]);
+    assert!(scan.stdout.contains("Busy, not nuked:"));
+    assert!(scan.stdout.contains("small.log"));
+    assert!(scan.stdout.contains("Total Bytes: 10 B\n"));
+}
+
+#[test]
+fn multiple_starting_directories() {
+    let fixture = Fixture::new(&[
+        Dir("a/.git"),
+        File("a/.gitignore", "*.log\n"),
+        Sized("a/x.log", 10),
+        Sized("a/sub/z.log", 5),
+        Dir("b/.git"),
+        File("b/.gitignore", "*.log\n"),
+        Sized("b/y.log", 20),
+        File("dirs.txt", "# Repos to clean\na\n\n  b  \n"),
+    ]);
+    let banner = format!(
+        "scanning for targets from {:?}",
+        vec![fixture.path("a"), fixture.path("b")]
+    );
+
+    // Overlapping and repeated directories are only scanned once
+    let scan = fixture.scan(&["-d", "a", "-d", "b", "-d", "a/sub", "-d", "b"]);
+    assert_eq!(scan.paths(), vec!["a/sub/z.log", "a/x.log", "b/y.log"]);
+    assert!(scan.stdout.contains(&banner));
+
+    // Each starting directory gets a subtotal
+    assert!(scan.stdout.contains("Starting directories:"));
+    assert!(scan
+        .stdout
+        .contains(&format!("  {:10} {:?}\n", "15 B", fixture.path("a"))));
+    assert!(scan
+        .stdout
+        .contains(&format!("  {:10} {:?}\n", "20 B", fixture.path("b"))));
+
+    // Directories can also be listed in a file, skipping comments and blank lines
+    let scan = fixture.scan(&["--directories-from", "dirs.txt", "-d", "a/sub"]);
+    assert_eq!(scan.paths(), vec!["a/sub/z.log", "a/x.log", "b/y.log"]);
+    assert!(scan.stdout.contains(&banner));
+
+    // Or read from stdin, once for both the banner and the scan
+    let output = fixture.run_with_input(&["--directories-from", "-", "--dry-run"], "a\nb\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&banner), "{}", stdout);
+    assert!(stdout.contains(&format!("{:?}", fixture.path("b/y.log"))), "{}", stdout);
+
+    // A single starting directory has no subtotals
+    let scan = fixture.scan(&["-d", "b"]);
+    assert_eq!(scan.paths(), vec!["b/y.log"]);
+    assert!(!scan.stdout.contains("Starting directories:"));
+}
+
+#[test]
+fn excluded_directories() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.log\nbuild/\n"),
+        Sized("a.log", 1),
+        Sized("vendor/b.log", 1),
+        Sized("src/vendor/c.log", 1),
+        Sized("build/out", 1),
+    ]);
+    assert_eq!(
+        fixture.scan(&[]).paths(),
+        vec!["a.log", "build", "src/vendor/c.log", "vendor/b.log"]
+    );
+
+    // Unanchored globs match at any depth, anchored ones only below the starting directory
+    assert_eq!(
+        fixture.scan(&["--exclude-dir", "vendor"]).paths(),
+        vec!["a.log", "build"]
+    );
+    assert_eq!(
+        fixture.scan(&["--exclude-dir", "/vendor"]).paths(),
+        vec!["a.log", "build", "src/vendor/c.log"]
+    );
+    assert_eq!(
+        fixture.scan(&["--exclude-dir", "src/vendor"]).paths(),
+        vec!["a.log", "build", "vendor/b.log"]
+    );
+
+    // Excluded directories aren't nuked even if they're ignored
+    assert_eq!(
+        fixture
+            .scan(&["--exclude-dir", "build", "--exclude-dir", "vendor"])
+            .paths(),
+        vec!["a.log"]
+    );
+}
+
+#[test]
+fn max_depth() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.log\nout/\n"),
+        Sized("a.log", 1),
+        Sized("out/deep/x", 200),
+        Sized("src/b.log", 1),
+        Sized("src/deeper/c.log", 1),
+        Sized("src/deeper/vendored.bin", 1000),
+    ]);
+
+    // Nothing below the starting directory is evaluated
+    assert!(fixture.scan(&["--max-depth", "0"]).candidates.is_empty());
+
+    // Only direct children are evaluated, ignored directories are still sized completely
+    let scan = fixture.scan(&["--max-depth", "1"]);
+    assert_eq!(scan.paths(), vec!["a.log", "out"]);
+    assert_eq!(scan.candidates["out"], 200);
+
+    // Files beyond the limit still count towards the scanned tree, so `out` isn't mistaken for most of it
+    let scan = fixture.scan(&["--max-depth", "1", "--large-min-size", "0"]);
+    assert!(!scan.stdout.contains("--allow-large"), "{}", scan.stdout);
+
+    assert_eq!(
+        fixture.scan(&["--max-depth", "2"]).paths(),
+        vec!["a.log", "out", "src/b.log"]
+    );
+}
+
+#[test]
+fn presets_stop_at_nested_checkouts() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File("setup.py", ""),
+        Sized("__pycache__/a.pyc", 1),
+        Sized("tools/__pycache__/b.pyc", 1),
+        // A vendored checkout isn't a python project just because it sits inside one
+        Dir("vendor/lib/.git"),
+        Sized("vendor/lib/__pycache__/c.pyc", 1),
+    ]);
+
+    assert_eq!(
+        fixture.scan(&["--preset", "python"]).paths(),
+        vec!["__pycache__", "tools/__pycache__"]
+    );
+}
+
+#[test]
+fn descend_whitelisted() {
+    let fixture = Fixture::new(&[
+        Dir(".git"),
+        File(".gitignore", "*.log\n"),
+        File(".gitnuke", "!/keep/\n"),
+        Sized("a.log", 1),
+        Sized("keep/b.log", 1),
+        Sized("keep/deeper/c.log", 1),
+        Sized(".git/d.log", 1),
+        // A checkout inside the whitelisted directory, with its own ignores
+        File("keep/sub/.gitignore", "*.log\n"),
+        Sized("keep/sub/f.log", 1),
+        Sized("keep/sub/.git/e.log", 1),
+    ]);
+
+    // Whitelisted directories are left alone by default
+    assert_eq!(fixture.scan(&[]).paths(), vec!["a.log"]);
+
+    // Ignored paths inside them are nuked with the flag, but .git is never walked
+    assert_eq!(
+        fixture.scan(&["--descend-whitelisted"]).paths(),
+        vec!["a.log", "keep/b.log", "keep/deeper/c.log", "keep/sub/f.log"]
+    );
+}